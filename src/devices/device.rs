@@ -1,8 +1,103 @@
 use crate::common::*;
 use crate::fdm1D::tridiag::MatTriDiag;
+use crate::fdm1D::blocktridiag::MatBlockTriDiag;
 use crate::semiconductor::*;
 use crate::fdm1D::*;
 use super::state::*;
+use super::interface::*;
+
+// scheme used to solve the drift-diffusion equations under bias
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SolverMode
+{
+    // decoupled poission and continuity solves, robust at low injection
+    Gummel,
+    // fully coupled newton, converges at high injection
+    Coupled,
+}
+
+// implicit time integration scheme of transient simulations
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeScheme
+{
+    BackwardEuler,
+    TRBDF2,
+}
+
+// rule that places the band edges of each layer relative to the previous one, the offsets are those of the
+// relaxed layers and strain shifts the edges after the alignment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BandAlignment
+{
+    // Anderson's rule, Ec = -electron affinity
+    ElectronAffinity,
+    // the conduction band takes this fraction of the band gap difference, e.g. 0.65 for GaAs/AlGaAs
+    ConductionOffsetRatio(f64),
+    // Ev of every layer on the scale of the valence band offset table, valence_band_offset of the bulks
+    ValenceBandOffsets,
+    // no valence band offset, the common anion rule
+    CommonAnion,
+}
+
+impl BandAlignment
+{
+    // shift of the band edges of bulk to align it to the aligned (Ec, Ev) before it, None for the first layer
+    pub fn band_shift(&self, previous_edges:Option<(f64, f64)>, bulk:&Bulk) -> f64
+    {
+        match (self, previous_edges) {
+            (BandAlignment::ValenceBandOffsets, _) => {
+                bulk.valence_band_offset.expect("Error: valence band offset alignment needs the valence_band_offset of every layer") - bulk.Ev
+            }
+            (_, None) | (BandAlignment::ElectronAffinity, _) => 0.0,
+            (BandAlignment::ConductionOffsetRatio(ratio), Some((Ec, Ev))) => Ec + ratio * (bulk.band_gap - (Ec - Ev)) - bulk.Ec,
+            (BandAlignment::CommonAnion, Some((_, Ev))) => Ev - bulk.Ev,
+        }
+    }
+}
+
+// tolerances of the adaptive mesh refinement, a link is refined when the linear interpolation error of the potential
+// h^2 |psi''| / 8 is above potential_tol or the log of a carrier density changes by more than log_density_tol across it,
+// the curvature is the larger of the discrete one and -charge / epsilon
+// nodes are removed when both of their links are below coarsen_fraction of the tolerances
+#[derive(Debug, Clone, Copy)]
+pub struct RefinementCriteria
+{
+    pub potential_tol:f64,
+    pub log_density_tol:f64,
+    pub coarsen_fraction:f64,
+    pub min_step:f64,
+    pub max_step:f64,
+}
+
+impl RefinementCriteria
+{
+    pub fn create(potential_tol:f64, log_density_tol:f64, min_step:f64, max_step:f64) -> RefinementCriteria
+    {
+        RefinementCriteria {
+            potential_tol,
+            log_density_tol,
+            coarsen_fraction:0.1,
+            min_step,
+            max_step
+        }
+    }
+}
+
+// implicit discretization of the carrier density time derivative, dn/dt = coeff * n - history_n
+struct TimeTerm
+{
+    coeff:f64,
+    history_n:VecD,
+    history_p:VecD,
+}
+
+impl TimeTerm
+{
+    fn rates(&self, n:&VecD, p:&VecD) -> (VecD, VecD)
+    {
+        (self.coeff * n - &self.history_n, self.coeff * p - &self.history_p)
+    }
+}
 
 pub struct Device {
     bulk_layers:Vec<Semiconductor>,
@@ -12,30 +107,160 @@ pub struct Device {
     epsilon:VecD,
     vacc_Ec:VecD,
     vacc_Ev:VecD,
+    // lattice constant the layers are strained to, layers are relaxed without a substrate
+    substrate_lattice_constant:Option<f64>,
+    alignment:BandAlignment,
+    // relaxed aligned (Ec, Ev) at the end of the last layer
+    aligned_edges:Option<(f64, f64)>,
+
+    // nodes at the boundaries between layers
+    interface_nodes:Vec<usize>,
+    // interfaces with their node
+    interfaces:Vec<(usize, Interface)>,
 
     pub net_doping:VecD,
+    // interface sheet charge per node (C/m^2), the bound polarization charge at the layer boundaries
+    pub sheet_charge:VecD,
+    // heavy hole minus light hole band edge per node from the strain
+    pub valence_splitting:VecD,
 
     pub mesh:Mesh,
     pub steady_state:State,
+    pub biased_state:State,
     pub full_width:f64,
+
+    pub solver_mode:SolverMode,
+    // gummel iterations run before the coupled newton iterations
+    pub gummel_warmup_iter:usize,
 }
 
 impl Device{
 
-    fn total_charge_vec(&self, fermi_lvl:f64, potential:&VecD) -> VecD
+    fn total_charge_qf_vec(&self, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD, potential:&VecD) -> VecD
     {
         self.bulk_layers.iter()
-            .map(|layer| layer.total_charge_vec(&self.mesh, fermi_lvl, &potential, self.temp))
-            .fold(self.mesh.zeroVec(), |acc, charge| acc + charge)
+            .map(|layer| layer.total_charge_qf_vec(&self.mesh, fermi_lvl_n, fermi_lvl_p, &potential, self.temp))
+            .fold(self.interface_charge_derivative_vecs(potential, fermi_lvl_n, fermi_lvl_p).0, |acc, charge| acc + charge)
+    }
+
+    fn total_charge_derivative_pot_qf_vec(&self, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD, potential:&VecD) -> VecD
+    {
+        let [interface_derivative, _, _] = self.interface_charge_derivative_vecs(potential, fermi_lvl_n, fermi_lvl_p).1;
+
+        self.bulk_layers.iter()
+            .map(|layer| layer.total_charge_derivative_pot_qf_vec(&self.mesh, fermi_lvl_n, fermi_lvl_p, &potential, self.temp))
+            .fold(interface_derivative, |acc, charge| acc + charge)
+    }
+
+    // charge density of the interface traps over the poission control width of their node and its
+    // derivatives wrt [potential, fermi_lvl_n / Q, fermi_lvl_p / Q], the contacts carry no interface charge
+    fn interface_charge_derivative_vecs(&self, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD) -> (VecD, [VecD; 3])
+    {
+        let mut charge = self.mesh.zeroVec();
+        let mut derivative = [self.mesh.zeroVec(), self.mesh.zeroVec(), self.mesh.zeroVec()];
+
+        for (i, interface) in self.interfaces.iter()
+        {
+            let i = *i;
+            if i == 0 || i == self.mesh.lastIdx()
+            {
+                continue;
+            }
+
+            let midgap = 0.5 * (self.vacc_Ec[i] + self.vacc_Ev[i]) - constants::Q * potential[i];
+            let (sheet_charge, sheet_derivative) = interface.trap_charge_derivative(midgap, fermi_lvl_n[i], fermi_lvl_p[i], self.temp);
+            let control_width = self.poissionProb.control_width[i];
+
+            charge[i] += sheet_charge / control_width;
+            for k in 0..3
+            {
+                derivative[k][i] += sheet_derivative[k] / control_width;
+            }
+        }
+
+        (charge, derivative)
     }
 
-    fn total_charge_derivative_pot_vec(&self, fermi_lvl:f64, potential:&VecD) -> VecD
+    fn total_charge_derivative_field_qf_vec(&self, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD, potential:&VecD) -> VecD
     {
         self.bulk_layers.iter()
-            .map(|layer| layer.total_charge_derivative_pot_vec(&self.mesh, fermi_lvl, &potential, self.temp))
+            .map(|layer| layer.total_charge_derivative_field_qf_vec(&self.mesh, fermi_lvl_n, fermi_lvl_p, &potential, self.temp))
             .fold(self.mesh.zeroVec(), |acc, charge| acc + charge)
     }
 
+    fn current_vec(&self, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD) -> (VecD, VecD)
+    {
+        self.bulk_layers.iter()
+            .map(|layer| layer.current_vec(&self.mesh, potential, fermi_lvl_n, fermi_lvl_p, self.temp))
+            .fold((self.mesh.zeroVec(), self.mesh.zeroVec()), |acc, J| (acc.0 + J.0, acc.1 + J.1))
+    }
+
+    fn electron_conductance_vec(&self, potential:&VecD, fermi_lvl_n:&VecD, reference:f64) -> VecD
+    {
+        self.bulk_layers.iter()
+            .map(|layer| layer.electron_conductance_vec(&self.mesh, potential, fermi_lvl_n, reference, self.temp))
+            .fold(self.mesh.zeroVec(), |acc, conductance| acc + conductance)
+    }
+
+    fn hole_conductance_vec(&self, potential:&VecD, fermi_lvl_p:&VecD, reference:f64) -> VecD
+    {
+        self.bulk_layers.iter()
+            .map(|layer| layer.hole_conductance_vec(&self.mesh, potential, fermi_lvl_p, reference, self.temp))
+            .fold(self.mesh.zeroVec(), |acc, conductance| acc + conductance)
+    }
+
+    fn recombination_vec(&self, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD) -> VecD
+    {
+        self.bulk_layers.iter()
+            .map(|layer| layer.recombination_vec(&self.mesh, potential, fermi_lvl_n, fermi_lvl_p, self.temp))
+            .fold(self.mesh.zeroVec(), |acc, rate| acc + rate)
+    }
+
+    // link impact ionization generation rates distributed to the nodes, every link gives half of its volume to each end
+    fn impact_generation_vec(&self, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD) -> VecD
+    {
+        let link_generation = self.bulk_layers.iter()
+            .map(|layer| layer.impact_generation_vec(&self.mesh, potential, fermi_lvl_n, fermi_lvl_p, self.temp))
+            .fold(self.mesh.zeroVec(), |acc, rate| acc + rate);
+        let h = self.mesh.calcStepVec();
+
+        let mut generation = self.mesh.zeroVec();
+        for i in 1..self.mesh.lastIdx()
+        {
+            generation[i] = (link_generation[i - 1] * h[i - 1] + link_generation[i] * h[i]) / (h[i - 1] + h[i]);
+        }
+
+        generation
+    }
+
+    fn band_to_band_vec(&self, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD) -> VecD
+    {
+        self.bulk_layers.iter()
+            .map(|layer| layer.band_to_band_vec(&self.mesh, potential, fermi_lvl_n, fermi_lvl_p, self.temp))
+            .fold(self.mesh.zeroVec(), |acc, rate| acc + rate)
+    }
+
+    // electron and hole mobility per node, interface nodes take the mobility of the first layer containing them
+    fn mobility_vec(&self, potential:&VecD) -> (VecD, VecD)
+    {
+        self.bulk_layers.iter()
+            .map(|layer| layer.mobility_vec(&self.mesh, potential, self.temp))
+            .fold((self.mesh.zeroVec(), self.mesh.zeroVec()), |acc, mobility| (
+                acc.0.zip_map(&mobility.0, |a, b| if a == 0.0 { b } else { a }),
+                acc.1.zip_map(&mobility.1, |a, b| if a == 0.0 { b } else { a })
+            ))
+    }
+
+    // net recombination rate at a node and its derivatives wrt [potential, fermi_lvl_n / Q, fermi_lvl_p / Q]
+    fn recombination_derivative(&self, i:usize, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD) -> (f64, [f64; 3])
+    {
+        let x = self.mesh.points[i];
+
+        self.bulk_layers.iter()
+            .map(|layer| layer.recombination_derivative(x, fermi_lvl_n[i], fermi_lvl_p[i], potential[i], self.temp))
+            .fold((0.0, [0.0; 3]), |acc, r| (acc.0 + r.0, [acc.1[0] + r.1[0], acc.1[1] + r.1[1], acc.1[2] + r.1[2]]))
+    }
+
     pub fn create(temp:f64) -> Device
     {
         Device {
@@ -46,56 +271,186 @@ impl Device{
             epsilon:VecD::default(),
             vacc_Ec:VecD::default(),
             vacc_Ev:VecD::default(),
+            substrate_lattice_constant:None,
+            alignment:BandAlignment::ElectronAffinity,
+            aligned_edges:None,
+            interface_nodes:Vec::new(),
+            interfaces:Vec::new(),
             net_doping:VecD::default(),
+            sheet_charge:VecD::default(),
+            valence_splitting:VecD::default(),
             mesh:Mesh::create(vec![0.0]),
             steady_state:State::default(),
+            biased_state:State::default(),
             full_width:0.0,
+            solver_mode:SolverMode::Gummel,
+            gummel_warmup_iter:3,
         }
     }
 
-    pub fn push_bulk_layer(&mut self, mut layer: Semiconductor, width:f64, samples:u32)
+    // layers pushed after this are grown pseudomorphically on the substrate and strained to its lattice constant
+    pub fn set_substrate(&mut self, substrate:&Bulk)
     {
-        self.mesh.extend(
-            (0..samples).map(|i| self.last_pos + f64::from(i + 1) * (width / f64::from(samples)))
-            .collect()
-        );
+        if !self.bulk_layers.is_empty()
+        {
+            panic!("Error: the substrate must be set before the layers are pushed");
+        }
+
+        let deformation = substrate.deformation.expect("Error: the substrate has no lattice constant");
+        self.substrate_lattice_constant = Some(deformation.lattice_constant);
+    }
 
-        if self.epsilon.is_empty()
+    // band alignment of the layers pushed after this, the electron affinity rule by default
+    pub fn set_band_alignment(&mut self, alignment:BandAlignment)
+    {
+        if !self.bulk_layers.is_empty()
         {
-            self.epsilon = VecD::from_column_slice(&[layer.bulk.epsilon]);
-            self.vacc_Ec = VecD::from_column_slice(&[layer.bulk.Ec]);
-            self.vacc_Ev = VecD::from_column_slice(&[layer.bulk.Ev]);
+            panic!("Error: the band alignment must be set before the layers are pushed");
+        }
+
+        self.alignment = alignment;
+    }
+
+    // interface at the end of the last pushed layer, the boundary to the next layer, which must be pushed before solving
+    // the fixed charge adds to the sheet charge and the traps follow the quasi-fermi levels at the boundary node
+    pub fn push_interface(&mut self, interface:Interface)
+    {
+        if self.bulk_layers.is_empty()
+        {
+            panic!("Error: an interface must follow a layer");
+        }
+
+        self.interfaces.push((self.mesh.lastIdx(), interface));
+    }
+
+    // interfaces are only placed between layers, the fixed charge is added to the sheet charge when the next layer is pushed
+    fn check_interfaces(&self)
+    {
+        if self.interfaces.iter().any(|(interface_idx, _)| !self.interface_nodes.contains(interface_idx))
+        {
+            panic!("Error: an interface must be followed by a layer");
+        }
+    }
+
+    pub fn push_bulk_layer(&mut self, mut layer: Semiconductor, width:f64, mesh_spec:&MeshSpec)
+    {
+        // layers are simulated at the device temperature
+        layer.set_temperature(self.temp);
+
+        // the last point is placed at the end of the layer exactly, so that the boundary node is inside both layers
+        // the first point is the last node of the previous layer
+        let layer_mesh = Mesh::from_spec(self.last_pos, self.last_pos + width, mesh_spec);
+        let samples = layer_mesh.lastIdx();
+        self.mesh.extend(layer_mesh.points[1..].to_vec());
+
+        // the node at the boundary belongs to the previous layer
+        layer.set_bulk_range(self.last_pos, self.last_pos + width, self.bulk_layers.is_empty());
+
+        // the node at the start of the layer is the last node of the previous layer
+        let first_idx = self.mesh.len() - samples;
+        layer.sample_grading(&self.mesh.points[first_idx - 1..]);
+
+        let alignment = self.alignment;
+        self.aligned_edges = Some(layer.align_bands(self.aligned_edges, |previous_edges, bulk| alignment.band_shift(previous_edges, bulk)));
+
+        if let Some(lattice_constant) = self.substrate_lattice_constant
+        {
+            layer.apply_strain(lattice_constant);
         }
 
-        self.epsilon.extend(
-            (0..samples).map(|_| layer.bulk.epsilon)
-        );
-        self.vacc_Ec.extend(
-            (0..samples).map(|_| layer.bulk.Ec)
-        );
-        self.vacc_Ev.extend(
-            (0..samples).map(|_| layer.bulk.Ev)
-        );
-        
-        layer.set_bulk_range(self.last_pos, self.last_pos + width);
         self.bulk_layers.push(layer);
 
         self.last_pos += width;
         self.full_width += width;
+
+        self.update_node_parameters();
+    }
+
+    // per node parameters from the layers, a node at a boundary belongs to the layer before it
+    // band edges include the strain and the band gap narrowing of the layer's doping, the sheet charge holds the
+    // bound polarization charge and the fixed interface charge
+    fn update_node_parameters(&mut self)
+    {
+        let node_layer = |x:f64| self.bulk_layers.iter().find(|layer| layer.is_inside(x)).expect("Error: mesh point outside of the layers");
+        let valence_splitting = |bulk:&Bulk| match (self.substrate_lattice_constant, &bulk.deformation) {
+            (Some(lattice_constant), Some(deformation)) => {
+                let shift = deformation.band_edge_shift(lattice_constant);
+                shift.1 - shift.2
+            }
+            _ => 0.0,
+        };
+
+        self.epsilon = self.mesh.makeVecFn(|x, _| node_layer(x).bulk_at(x).epsilon);
+        self.vacc_Ec = self.mesh.makeVecFn(|x, _| node_layer(x).bulk_at(x).Ec - node_layer(x).band_edge_shift(x).0);
+        self.vacc_Ev = self.mesh.makeVecFn(|x, _| node_layer(x).bulk_at(x).Ev + node_layer(x).band_edge_shift(x).1);
+        self.valence_splitting = self.mesh.makeVecFn(|x, _| valence_splitting(node_layer(x).bulk_at(x)));
+
+        let mut sheet_charge = self.mesh.zeroVec();
+        let mut interface_nodes = Vec::new();
+
+        // bound polarization charge P_prev - P_new at the boundaries between layers
+        for layers in self.bulk_layers.windows(2)
+        {
+            let position = layers[1].range().0;
+            let interface_idx = self.mesh.find_point(position).expect("Error: no mesh point at a layer boundary");
+
+            sheet_charge[interface_idx] += layers[0].bulk_at(position).total_polarization(self.substrate_lattice_constant) - 
+                layers[1].bulk_at(position).total_polarization(self.substrate_lattice_constant);
+            interface_nodes.push(interface_idx);
+        }
+
+        // bound charge -dP/dx of graded layers, each link adds half of P[i] - P[i + 1] to both of its nodes
+        for layer in self.bulk_layers.iter().filter(|layer| layer.is_graded())
+        {
+            let (begin_pos, end_pos) = layer.range();
+
+            for i in 0..self.mesh.lastIdx()
+            {
+                let (x0, x1) = (self.mesh.points[i], self.mesh.points[i + 1]);
+
+                if x0 < begin_pos || x1 > end_pos
+                {
+                    continue;
+                }
+
+                let link_charge = 0.5 * (layer.bulk_at(x0).total_polarization(self.substrate_lattice_constant) - 
+                    layer.bulk_at(x1).total_polarization(self.substrate_lattice_constant));
+
+                sheet_charge[i] += link_charge;
+                sheet_charge[i + 1] += link_charge;
+            }
+        }
+
+        for (interface_idx, interface) in self.interfaces.iter()
+        {
+            sheet_charge[*interface_idx] += interface.fixed_charge;
+        }
+
+        self.sheet_charge = sheet_charge;
+        self.interface_nodes = interface_nodes;
     }
 
     pub fn calc_steady_state(&mut self, charge_tol:f64, rel_potential_tol:f64, max_iter:usize)
     {
+        self.calc_steady_state_from(self.mesh.zeroVec(), charge_tol, rel_potential_tol, max_iter);
+    }
+
+    // steady state starting the poission iterations from the potential, the contact values are set here
+    fn calc_steady_state_from(&mut self, mut potential:VecD, charge_tol:f64, rel_potential_tol:f64, max_iter:usize)
+    {
+        self.check_interfaces();
+
         // prepare the poission problem
         self.poissionProb = PoissionProblem::create(&self.mesh, &self.epsilon);
+        self.poissionProb.set_sheet_charge(&self.sheet_charge);
 
         let interface_layer_left = self.bulk_layers.first().expect("No layers initialized! ");
         let interface_layer_right = self.bulk_layers.last().expect("No layers initialized! ");
 
         // the potential at x = 0 is taken as the reference.
         self.steady_state.fermi_lvl = calcRootBisection(
-            interface_layer_left.bulk.Ev, 
-            interface_layer_left.bulk.Ec, 
+            interface_layer_left.bulk_at(0.0).Ev, 
+            interface_layer_left.bulk_at(0.0).Ec, 
             |mu| interface_layer_left.total_charge(0.0, mu, 0.0, self.temp), 
             rel_potential_tol,
             charge_tol,
@@ -103,8 +458,8 @@ impl Device{
         ).expect("No fermi_lvl in max_iter using bisection method");
 
         self.steady_state.built_in_potential = calcRootBisection(
-            -interface_layer_right.bulk.band_gap / constants::Q,
-            interface_layer_right.bulk.band_gap / constants::Q,
+            -interface_layer_right.bulk_at(self.full_width).band_gap / constants::Q,
+            interface_layer_right.bulk_at(self.full_width).band_gap / constants::Q,
             |V| interface_layer_right.total_charge(self.full_width, self.steady_state.fermi_lvl, V, self.temp), 
             rel_potential_tol,
             charge_tol,
@@ -113,75 +468,1145 @@ impl Device{
 
         let sample_last_idx = self.mesh.lastIdx();
 
-        let mut potential = self.mesh.zeroVec();
-        let mut charge = self.total_charge_vec(self.steady_state.fermi_lvl, &potential);
+        let fermi_lvl = self.mesh.makeVec(self.steady_state.fermi_lvl);
         
         potential[0] = 0.0;
         potential[sample_last_idx] = self.steady_state.built_in_potential;
-        
-        for i in 0..max_iter {
-            let charge_derivative = self.total_charge_derivative_pot_vec(self.steady_state.fermi_lvl, &potential);
 
-            // calculate the residual
-            let mut residual = self.poissionProb.residue(&potential, &charge);
-            residual[0] = 0.0;
-            residual[sample_last_idx] = 0.0;
+        (self.steady_state.potential, _) = self.solve_poission(potential, &fermi_lvl, &fermi_lvl, charge_tol, rel_potential_tol, max_iter)
+            .expect("Poission equation did not converge!");
+        self.steady_state.fermi_lvl_n = fermi_lvl.clone();
+        self.steady_state.fermi_lvl_p = fermi_lvl;
+        self.steady_state.applied_voltage = 0.0;
 
-            let poission_mat = self.poissionProb.operator.clone();
-            let mut jacobian:MatTriDiag = (poission_mat.0, &charge_derivative + poission_mat.1, poission_mat.2);
+        let mut steady_state = std::mem::take(&mut self.steady_state);
+        self.fill_state(&mut steady_state);
+        self.steady_state = steady_state;
 
-            jacobian.1[0] = 1.0;
-            jacobian.2[0] = 0.0;
+        self.net_doping = self.bulk_layers.iter()
+            .map(|y| y.total_dopant_charge_vec(&self.mesh) / constants::Q)
+            .sum();
 
-            jacobian.1[sample_last_idx] = 1.0;
-            jacobian.0[sample_last_idx - 1] = 0.0;
+    }
 
-            let deltaV = tridiag::solve(&jacobian, &mut self.mesh.zeroVec(), residual);
+    // steady state on a mesh adapted to it in up to max_passes passes, returns the number of passes that changed the mesh
+    pub fn calc_steady_state_adaptive(&mut self, criteria:&RefinementCriteria, max_passes:usize, charge_tol:f64, rel_potential_tol:f64, max_iter:usize) -> usize
+    {
+        self.calc_steady_state(charge_tol, rel_potential_tol, max_iter);
 
-            let w = 1.5;
-            potential -= w * &deltaV;
+        for pass in 0..max_passes
+        {
+            match self.adapted_mesh(&self.steady_state, criteria) {
+                Some(mesh) => self.remesh(mesh),
+                None => return pass,
+            }
 
-            // reinforce the boundary conditions
-            potential[0] = 0.0;
-            potential[sample_last_idx] = self.steady_state.built_in_potential;
+            self.calc_steady_state_from(self.steady_state.potential.clone(), charge_tol, rel_potential_tol, max_iter);
+        }
 
-            let prev_charge = charge;
-            charge = self.total_charge_vec(self.steady_state.fermi_lvl, &potential);
+        max_passes
+    }
 
-            if (prev_charge - &charge).abs().max() < charge_tol && 
-                deltaV.abs().max() / potential.abs().max() < rel_potential_tol &&
-                i > 1
-            {
-                break;
+    // biased state on a mesh adapted to it in up to max_passes passes, the steady state is solved again on every
+    // new mesh, returns the number of passes that changed the mesh
+    pub fn calc_biased_state_adaptive(&mut self, applied_voltage:f64, criteria:&RefinementCriteria, max_passes:usize, charge_tol:f64, rel_potential_tol:f64, max_iter:usize) -> usize
+    {
+        self.calc_biased_state(applied_voltage, charge_tol, rel_potential_tol, max_iter);
+
+        for pass in 0..max_passes
+        {
+            match self.adapted_mesh(&self.biased_state, criteria) {
+                Some(mesh) => self.remesh(mesh),
+                None => return pass,
             }
 
-            if i == max_iter - 1
+            self.calc_steady_state_from(self.steady_state.potential.clone(), charge_tol, rel_potential_tol, max_iter);
+            self.calc_biased_state(applied_voltage, charge_tol, rel_potential_tol, max_iter);
+        }
+
+        max_passes
+    }
+
+    // refinement indicator of each link for the state, above 1 where the link should be refined
+    pub fn refinement_indicator(&self, state:&State, criteria:&RefinementCriteria) -> VecD
+    {
+        let h = self.mesh.calcStepVec();
+        let sample_last_idx = self.mesh.lastIdx();
+
+        let curvature = self.mesh.makeVecFn(|_, i| {
+            if i == 0 || i == sample_last_idx
             {
-                panic!("Steady state did not converge!");
+                return 0.0;
             }
+
+            let discrete = ((state.potential[i + 1] - state.potential[i]) / h[i] - (state.potential[i] - state.potential[i - 1]) / h[i - 1]) / 
+                (0.5 * (h[i - 1] + h[i]));
+
+            discrete.abs().max((state.charge[i] / self.epsilon[i]).abs())
+        });
+
+        let mut indicator = self.mesh.zeroVec();
+
+        for i in 0..sample_last_idx
+        {
+            let potential_error = h[i] * h[i] * curvature[i].max(curvature[i + 1]) / 8.0;
+            let log_density_step = (state.n[i + 1] / state.n[i]).ln().abs().max((state.p[i + 1] / state.p[i]).ln().abs());
+
+            indicator[i] = (potential_error / criteria.potential_tol).max(log_density_step / criteria.log_density_tol);
         }
 
-        self.steady_state.potential = potential.clone();
-        self.steady_state.charge = charge.clone();
-        self.steady_state.Ec = &self.vacc_Ec - constants::Q * &potential;
-        self.steady_state.Ev = &self.vacc_Ev - constants::Q * &potential;
-        
-        self.steady_state.n = self.mesh.makeVecFn( |x, i| 
-                            self.bulk_layers.iter()
-                            .map(|y| y.electron_conc(x, self.steady_state.fermi_lvl, self.steady_state.potential[i], self.temp))
-                            .sum()
-        );
+        indicator
+    }
 
-        self.steady_state.p = self.mesh.makeVecFn( |x, i| 
-            self.bulk_layers.iter()
-            .map(|y| y.hole_conc(x, self.steady_state.fermi_lvl, self.steady_state.potential[i], self.temp))
-            .sum()
-        );
+    // mesh adapted to the state that keeps the layer boundaries, None if nothing changes
+    fn adapted_mesh(&self, state:&State, criteria:&RefinementCriteria) -> Option<Mesh>
+    {
+        let indicator = self.refinement_indicator(state, criteria);
+
+        let mesh = self.mesh.adapt(&indicator, criteria.coarsen_fraction, criteria.min_step, criteria.max_step, &self.interface_nodes);
+
+        if mesh.points == self.mesh.points { None } else { Some(mesh) }
+    }
+
+    // move the device to a mesh that has nodes at the layer boundaries, the steady and biased states are
+    // interpolated to it and should be solved again, graded layers are sampled at the new nodes
+    pub fn remesh(&mut self, mut mesh:Mesh)
+    {
+        // the boundary nodes are moved onto the boundaries exactly, so that they stay in the layer before them
+        for layer in self.bulk_layers.iter().skip(1)
+        {
+            let position = layer.range().0;
+            let boundary_idx = mesh.find_point(position).expect("Error: the new mesh has no point at a layer boundary");
+            mesh.points[boundary_idx] = position;
+        }
+
+        for (interface_idx, _) in self.interfaces.iter_mut()
+        {
+            *interface_idx = mesh.find_point(self.mesh.points[*interface_idx]).expect("Error: the new mesh has no point at an interface");
+        }
+
+        let interpolate_state = |state:&mut State| {
+            if state.potential.len() == self.mesh.len()
+            {
+                state.potential = self.mesh.interpolate(&state.potential, &mesh);
+                state.fermi_lvl_n = self.mesh.interpolate(&state.fermi_lvl_n, &mesh);
+                state.fermi_lvl_p = self.mesh.interpolate(&state.fermi_lvl_p, &mesh);
+            }
+        };
+
+        let mut steady_state = std::mem::take(&mut self.steady_state);
+        let mut biased_state = std::mem::take(&mut self.biased_state);
+        interpolate_state(&mut steady_state);
+        interpolate_state(&mut biased_state);
+
+        self.mesh = mesh;
+
+        let alignment = self.alignment;
+        for layer in self.bulk_layers.iter_mut().filter(|layer| layer.is_graded())
+        {
+            let (begin_pos, end_pos) = layer.range();
+            let points = self.mesh.points.iter().copied().filter(|&x| x >= begin_pos && x <= end_pos).collect::<Vec<f64>>();
+
+            layer.resample_grading(&points, |previous_edges, bulk| alignment.band_shift(previous_edges, bulk), self.substrate_lattice_constant);
+        }
+
+        self.update_node_parameters();
 
+        self.poissionProb = PoissionProblem::create(&self.mesh, &self.epsilon);
+        self.poissionProb.set_sheet_charge(&self.sheet_charge);
         self.net_doping = self.bulk_layers.iter()
             .map(|y| y.total_dopant_charge_vec(&self.mesh) / constants::Q)
             .sum();
 
+        for state in [&mut steady_state, &mut biased_state]
+        {
+            if state.potential.len() == self.mesh.len()
+            {
+                self.fill_state(state);
+            }
+        }
+
+        self.steady_state = steady_state;
+        self.biased_state = biased_state;
+    }
+
+    // solve the drift-diffusion equations with applied_voltage on the left contact (x = 0)
+    // the right contact is grounded and both contacts are ohmic
+    // continues from the last biased state if there is one, otherwise from the steady state
+    pub fn calc_biased_state(&mut self, applied_voltage:f64, charge_tol:f64, rel_potential_tol:f64, max_iter:usize)
+    {
+        if !self.try_calc_biased_state(applied_voltage, charge_tol, rel_potential_tol, max_iter)
+        {
+            panic!("Biased state did not converge!");
+        }
+    }
+
+    // like calc_biased_state, but returns false instead of panicking if the solver does not converge
+    // the last biased state is kept in that case
+    pub fn try_calc_biased_state(&mut self, applied_voltage:f64, charge_tol:f64, rel_potential_tol:f64, max_iter:usize) -> bool
+    {
+        match self.solver_mode {
+            SolverMode::Gummel => self.try_biased_state_gummel(applied_voltage, charge_tol, rel_potential_tol, max_iter),
+            SolverMode::Coupled => self.try_biased_state_coupled(applied_voltage, charge_tol, rel_potential_tol, max_iter),
+        }
+    }
+
+    // solve the drift-diffusion equations like calc_biased_state, with decoupled gummel iterations
+    pub fn calc_biased_state_gummel(&mut self, applied_voltage:f64, charge_tol:f64, rel_potential_tol:f64, max_iter:usize)
+    {
+        if !self.try_biased_state_gummel(applied_voltage, charge_tol, rel_potential_tol, max_iter)
+        {
+            panic!("Biased state did not converge!");
+        }
+    }
+
+    fn try_biased_state_gummel(&mut self, applied_voltage:f64, charge_tol:f64, rel_potential_tol:f64, max_iter:usize) -> bool
+    {
+        self.check_interfaces();
+
+        if self.steady_state.potential.len() != self.mesh.len()
+        {
+            self.calc_steady_state(charge_tol, rel_potential_tol, max_iter);
+        }
+
+        let (mut potential, mut fermi_lvl_n, mut fermi_lvl_p) = self.biased_initial_guess(applied_voltage);
+
+        if !self.gummel_iterations(&mut potential, &mut fermi_lvl_n, &mut fermi_lvl_p, charge_tol, rel_potential_tol, max_iter, max_iter)
+        {
+            return false;
+        }
+
+        self.store_biased_state(applied_voltage, potential, fermi_lvl_n, fermi_lvl_p);
+        true
+    }
+
+    // gummel iterations, alternating the nonlinear poission equation with frozen quasi-fermi levels
+    // and linear continuity solves in slotboom variables with a frozen potential
+    // the contact values are taken from the initial quasi-fermi levels, returns true if converged in max_iter
+    fn gummel_iterations(&self, potential:&mut VecD, fermi_lvl_n:&mut VecD, fermi_lvl_p:&mut VecD, charge_tol:f64, rel_potential_tol:f64, max_iter:usize, poission_max_iter:usize) -> bool
+    {
+        let thermal_energy = constants::K * self.temp;
+        let sample_last_idx = self.mesh.lastIdx();
+
+        let left_fermi_lvl = fermi_lvl_n[0];
+        let right_fermi_lvl = fermi_lvl_n[sample_last_idx];
+        // reference energy of the slotboom variables, keeps them within floating point range
+        let reference = 0.5 * (left_fermi_lvl + right_fermi_lvl);
+
+        for i in 0..max_iter
+        {
+            match self.solve_poission(potential.clone(), fermi_lvl_n, fermi_lvl_p, charge_tol, rel_potential_tol, poission_max_iter) {
+                Some((next_potential, _)) => *potential = next_potential,
+                None => return false,
+            }
+
+            // recombination linearized around the current quasi-fermi levels in the slotboom variables
+            // source = q(R0 + dR/dphi (phi - phi0)), dR/dphi is positive so it adds to the diagonal
+            let phi_n0 = fermi_lvl_n.map(|Efn| f64::exp((Efn - reference) / thermal_energy));
+            let phi_p0 = fermi_lvl_p.map(|Efp| f64::exp((reference - Efp) / thermal_energy));
+            let mut n_linear_source = self.mesh.zeroVec();
+            let mut n_source = self.mesh.zeroVec();
+            let mut p_linear_source = self.mesh.zeroVec();
+            let mut p_source = self.mesh.zeroVec();
+            // impact ionization and tunneling generation are kept explicit, the currents of the initial guess are not used
+            // since the contact quasi-fermi levels jump there
+            let mut generation = self.band_to_band_vec(potential, fermi_lvl_n, fermi_lvl_p);
+            if i > 0
+            {
+                generation += self.impact_generation_vec(potential, fermi_lvl_n, fermi_lvl_p);
+            }
+
+            for k in 1..sample_last_idx
+            {
+                let (rate, rate_derivative) = self.recombination_derivative(k, potential, fermi_lvl_n, fermi_lvl_p);
+
+                // dphi_n / d(Efn / Q) = Q phi_n / kT and dphi_p / d(Efp / Q) = -Q phi_p / kT
+                n_linear_source[k] = rate_derivative[1] * thermal_energy / phi_n0[k];
+                p_linear_source[k] = -rate_derivative[2] * thermal_energy / phi_p0[k];
+                n_source[k] = constants::Q * (rate - generation[k]) - n_linear_source[k] * phi_n0[k];
+                p_source[k] = constants::Q * (rate - generation[k]) - p_linear_source[k] * phi_p0[k];
+            }
+
+            // electron continuity, phi_n = exp((Efn - reference) / kT)
+            let mut electron_prob = ContinuityProblem::create(&self.mesh, &self.electron_conductance_vec(potential, fermi_lvl_n, reference), &n_linear_source);
+            let phi_n = electron_prob.solve(
+                &n_source, 
+                f64::exp((left_fermi_lvl - reference) / thermal_energy), 
+                f64::exp((right_fermi_lvl - reference) / thermal_energy)
+            );
+            let next_fermi_lvl_n = phi_n.map(|phi| reference + thermal_energy * phi.ln());
+
+            // hole continuity, phi_p = exp((reference - Efp) / kT)
+            let mut hole_prob = ContinuityProblem::create(&self.mesh, &self.hole_conductance_vec(potential, fermi_lvl_p, reference), &p_linear_source);
+            let phi_p = hole_prob.solve(
+                &p_source, 
+                f64::exp((reference - left_fermi_lvl) / thermal_energy), 
+                f64::exp((reference - right_fermi_lvl) / thermal_energy)
+            );
+            let next_fermi_lvl_p = phi_p.map(|phi| reference - thermal_energy * phi.ln());
+
+            let delta_fermi_lvl = f64::max(
+                (&next_fermi_lvl_n - &*fermi_lvl_n).abs().max(), 
+                (&next_fermi_lvl_p - &*fermi_lvl_p).abs().max()
+            ) / constants::Q;
+
+            *fermi_lvl_n = next_fermi_lvl_n;
+            *fermi_lvl_p = next_fermi_lvl_p;
+
+            if delta_fermi_lvl / potential.abs().max() < rel_potential_tol && i > 0
+            {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    // solve the drift-diffusion equations like calc_biased_state, with fully coupled newton iterations
+    // the unknowns at every node are (potential, fermi_lvl_n / Q, fermi_lvl_p / Q) and the jacobian is block tridiagonal
+    pub fn calc_biased_state_coupled(&mut self, applied_voltage:f64, charge_tol:f64, rel_potential_tol:f64, max_iter:usize)
+    {
+        if !self.try_biased_state_coupled(applied_voltage, charge_tol, rel_potential_tol, max_iter)
+        {
+            panic!("Biased state did not converge!");
+        }
+    }
+
+    fn try_biased_state_coupled(&mut self, applied_voltage:f64, charge_tol:f64, rel_potential_tol:f64, max_iter:usize) -> bool
+    {
+        self.check_interfaces();
+
+        if self.steady_state.potential.len() != self.mesh.len()
+        {
+            self.calc_steady_state(charge_tol, rel_potential_tol, max_iter);
+        }
+
+        let (mut potential, mut fermi_lvl_n, mut fermi_lvl_p) = self.biased_initial_guess(applied_voltage);
+
+        // a few gummel iterations give a good initial guess for newton, unless they break down
+        let initial_guess = (potential.clone(), fermi_lvl_n.clone(), fermi_lvl_p.clone());
+        self.gummel_iterations(&mut potential, &mut fermi_lvl_n, &mut fermi_lvl_p, charge_tol, rel_potential_tol, self.gummel_warmup_iter, max_iter);
+
+        if [&potential, &fermi_lvl_n, &fermi_lvl_p].iter().any(|v| v.iter().any(|x| !x.is_finite()))
+        {
+            (potential, fermi_lvl_n, fermi_lvl_p) = initial_guess;
+        }
+
+        if !self.newton_iterations(&mut potential, &mut fermi_lvl_n, &mut fermi_lvl_p, None, charge_tol, rel_potential_tol, max_iter)
+        {
+            return false;
+        }
+
+        self.store_biased_state(applied_voltage, potential, fermi_lvl_n, fermi_lvl_p);
+        true
+    }
+
+    // time domain simulation from initial_state at t = 0, with voltage(t) on the left contact
+    // the time step adapts to keep the local truncation error of the carrier densities below lte_tol,
+    // relative to the local density (floored at 1e-6 of the peak density)
+    pub fn calc_transient(&mut self, initial_state:&State, voltage:impl Fn(f64) -> f64, scheme:TimeScheme, end_time:f64, time_step:f64, 
+        lte_tol:f64, charge_tol:f64, rel_potential_tol:f64, max_iter:usize) -> Vec<TransientFrame>
+    {
+        self.check_interfaces();
+
+        if self.steady_state.potential.len() != self.mesh.len()
+        {
+            self.calc_steady_state(charge_tol, rel_potential_tol, max_iter);
+        }
+
+        let sample_last_idx = self.mesh.lastIdx();
+        let min_time_step = 1e-12 * end_time;
+        // TR-BDF2 stage fraction
+        let gamma = 2.0 - f64::sqrt(2.0);
+        let order = match scheme {
+            TimeScheme::BackwardEuler => 1.0,
+            TimeScheme::TRBDF2 => 2.0,
+        };
+
+        let mut frames = vec![TransientFrame{ state:initial_state.clone(), time:0.0, time_step:0.0 }];
+
+        let mut time = 0.0;
+        let mut time_step = time_step;
+
+        let mut potential = initial_state.potential.clone();
+        let mut fermi_lvl_n = initial_state.fermi_lvl_n.clone();
+        let mut fermi_lvl_p = initial_state.fermi_lvl_p.clone();
+        let (mut n, mut p) = self.carrier_conc_vecs(&potential, &fermi_lvl_n, &fermi_lvl_p);
+        let (mut rate_n, mut rate_p) = self.carrier_rates(&potential, &fermi_lvl_n, &fermi_lvl_p);
+
+        while time < end_time
+        {
+            time_step = time_step.min(end_time - time);
+            let next_time = time + time_step;
+
+            // returns the solution, carrier densities, their rates and local truncation errors
+            let trial = match scheme {
+                TimeScheme::BackwardEuler => {
+                    let term = TimeTerm{ coeff:1.0 / time_step, history_n:&n / time_step, history_p:&p / time_step };
+
+                    self.implicit_stage(voltage(next_time), &term, &potential, &fermi_lvl_n, &fermi_lvl_p, charge_tol, rel_potential_tol, max_iter)
+                    .map(|(next_potential, next_fermi_lvl_n, next_fermi_lvl_p)| {
+                        let (next_n, next_p) = self.carrier_conc_vecs(&next_potential, &next_fermi_lvl_n, &next_fermi_lvl_p);
+                        let (next_rate_n, next_rate_p) = term.rates(&next_n, &next_p);
+
+                        let lte_n = 0.5 * time_step * (&next_rate_n - &rate_n);
+                        let lte_p = 0.5 * time_step * (&next_rate_p - &rate_p);
+
+                        (next_potential, next_fermi_lvl_n, next_fermi_lvl_p, next_n, next_p, next_rate_n, next_rate_p, lte_n, lte_p)
+                    })
+                }
+                TimeScheme::TRBDF2 => {
+                    // trapezoidal stage to time + gamma * time_step
+                    let trapezoidal_coeff = 2.0 / (gamma * time_step);
+                    let term = TimeTerm{ 
+                        coeff:trapezoidal_coeff, 
+                        history_n:trapezoidal_coeff * &n + &rate_n, 
+                        history_p:trapezoidal_coeff * &p + &rate_p
+                    };
+
+                    self.implicit_stage(voltage(time + gamma * time_step), &term, &potential, &fermi_lvl_n, &fermi_lvl_p, charge_tol, rel_potential_tol, max_iter)
+                    .and_then(|(stage_potential, stage_fermi_lvl_n, stage_fermi_lvl_p)| {
+                        let (stage_n, stage_p) = self.carrier_conc_vecs(&stage_potential, &stage_fermi_lvl_n, &stage_fermi_lvl_p);
+                        let (stage_rate_n, stage_rate_p) = term.rates(&stage_n, &stage_p);
+
+                        // BDF2 stage to time + time_step
+                        let bdf_scale = 1.0 / ((1.0 - gamma) * time_step);
+                        let term = TimeTerm{
+                            coeff:(2.0 - gamma) * bdf_scale,
+                            history_n:bdf_scale * (&stage_n / gamma - (1.0 - gamma).powi(2) / gamma * &n),
+                            history_p:bdf_scale * (&stage_p / gamma - (1.0 - gamma).powi(2) / gamma * &p),
+                        };
+
+                        self.implicit_stage(voltage(next_time), &term, &stage_potential, &stage_fermi_lvl_n, &stage_fermi_lvl_p, charge_tol, rel_potential_tol, max_iter)
+                        .map(|(next_potential, next_fermi_lvl_n, next_fermi_lvl_p)| {
+                            let (next_n, next_p) = self.carrier_conc_vecs(&next_potential, &next_fermi_lvl_n, &next_fermi_lvl_p);
+                            let (next_rate_n, next_rate_p) = term.rates(&next_n, &next_p);
+
+                            let lte_coeff = 2.0 * time_step * (-3.0 * gamma * gamma + 4.0 * gamma - 2.0) / (12.0 * (2.0 - gamma));
+                            let lte_n = lte_coeff * (&rate_n / gamma - &stage_rate_n / (gamma * (1.0 - gamma)) + &next_rate_n / (1.0 - gamma));
+                            let lte_p = lte_coeff * (&rate_p / gamma - &stage_rate_p / (gamma * (1.0 - gamma)) + &next_rate_p / (1.0 - gamma));
+
+                            (next_potential, next_fermi_lvl_n, next_fermi_lvl_p, next_n, next_p, next_rate_n, next_rate_p, lte_n, lte_p)
+                        })
+                    })
+                }
+            };
+
+            // error relative to the tolerance, a failed newton solve counts as a rejected step
+            let mut error = f64::INFINITY;
+
+            if let Some((next_potential, next_fermi_lvl_n, next_fermi_lvl_p, next_n, next_p, mut next_rate_n, mut next_rate_p, lte_n, lte_p)) = trial
+            {
+                let n_floor = 1e-6 * next_n.max();
+                let p_floor = 1e-6 * next_p.max();
+
+                error = 0.0;
+                for i in 1..sample_last_idx
+                {
+                    error = error
+                        .max(lte_n[i].abs() / (lte_tol * (next_n[i] + n_floor)))
+                        .max(lte_p[i].abs() / (lte_tol * (next_p[i] + p_floor)));
+                }
+
+                if error <= 1.0
+                {
+                    // the contacts are fixed, their rates are not defined
+                    next_rate_n[0] = 0.0;
+                    next_rate_n[sample_last_idx] = 0.0;
+                    next_rate_p[0] = 0.0;
+                    next_rate_p[sample_last_idx] = 0.0;
+
+                    let displacement_current = self.displacement_current(&next_potential, &potential, time_step);
+
+                    potential = next_potential;
+                    fermi_lvl_n = next_fermi_lvl_n;
+                    fermi_lvl_p = next_fermi_lvl_p;
+                    (n, p) = (next_n, next_p);
+                    (rate_n, rate_p) = (next_rate_n, next_rate_p);
+                    time = next_time;
+
+                    let mut state = self.make_state(voltage(time), potential.clone(), fermi_lvl_n.clone(), fermi_lvl_p.clone());
+                    state.current += displacement_current;
+
+                    frames.push(TransientFrame{ state, time, time_step });
+                }
+            }
+
+            // next time step from the error estimate
+            let factor = if error.is_finite() { (0.9 * error.powf(-1.0 / (order + 1.0))).clamp(0.2, 2.0) } else { 0.25 };
+            time_step *= factor;
+
+            if time_step < min_time_step
+            {
+                panic!("Transient time step is too small at t = {:e} s!", time);
+            }
+        }
+
+        frames
+    }
+
+    // newton solve of one implicit time stage with the left contact at applied_voltage
+    fn implicit_stage(&self, applied_voltage:f64, time_term:&TimeTerm, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD, 
+        charge_tol:f64, rel_potential_tol:f64, max_iter:usize) -> Option<(VecD, VecD, VecD)>
+    {
+        let mut potential = potential.clone();
+        let mut fermi_lvl_n = fermi_lvl_n.clone();
+        let mut fermi_lvl_p = fermi_lvl_p.clone();
+
+        self.apply_contacts(applied_voltage, &mut potential, &mut fermi_lvl_n, &mut fermi_lvl_p);
+
+        if self.newton_iterations(&mut potential, &mut fermi_lvl_n, &mut fermi_lvl_p, Some(time_term), charge_tol, rel_potential_tol, max_iter)
+        {
+            Some((potential, fermi_lvl_n, fermi_lvl_p))
+        }
+        else
+        {
+            None
+        }
+    }
+
+    // coupled newton iterations, returns true if converged in max_iter
+    fn newton_iterations(&self, potential:&mut VecD, fermi_lvl_n:&mut VecD, fermi_lvl_p:&mut VecD, time_term:Option<&TimeTerm>, 
+        charge_tol:f64, rel_potential_tol:f64, max_iter:usize) -> bool
+    {
+        let mut scratch = vec![blocktridiag::Block::zeros(); self.mesh.len()];
+        // limit the newton update of every unknown (in volts) far from the solution
+        let max_update = 10.0 * constants::thermal_pot(self.temp);
+
+        for i in 0..max_iter
+        {
+            let (jacobian, residual) = self.assemble_coupled(potential, fermi_lvl_n, fermi_lvl_p, time_term);
+            let poission_residual = residual.iter().step_by(3).fold(0.0, |acc:f64, r| acc.max(r.abs()));
+
+            let delta = match blocktridiag::try_solve(&jacobian, &mut scratch, residual) {
+                Some(delta) => delta.map(|d| d.clamp(-max_update, max_update)),
+                None => return false,
+            };
+
+            let largest_update = delta.abs().max();
+
+            if !largest_update.is_finite()
+            {
+                return false;
+            }
+
+            for k in 0..self.mesh.len()
+            {
+                potential[k] -= delta[3 * k];
+                fermi_lvl_n[k] -= constants::Q * delta[3 * k + 1];
+                fermi_lvl_p[k] -= constants::Q * delta[3 * k + 2];
+            }
+
+            if poission_residual < charge_tol && 
+                largest_update / potential.abs().max() < rel_potential_tol &&
+                i > 0
+            {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    // carrier density rates (dn/dt, dp/dt) from the continuity equations
+    fn carrier_rates(&self, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD) -> (VecD, VecD)
+    {
+        let (_, residual) = self.assemble_coupled(potential, fermi_lvl_n, fermi_lvl_p, None);
+
+        (
+            self.mesh.makeVecFn(|_, i| residual[3 * i + 1] / constants::Q),
+            self.mesh.makeVecFn(|_, i| -residual[3 * i + 2] / constants::Q)
+        )
+    }
+
+    // mean displacement current density between two potentials a time step apart
+    fn displacement_current(&self, potential:&VecD, prev_potential:&VecD, time_step:f64) -> f64
+    {
+        let h = self.mesh.calcStepVec();
+        let sample_last_idx = self.mesh.lastIdx();
+
+        (0..sample_last_idx)
+            .map(|i| {
+                let epsilon = 0.5 * (self.epsilon[i] + self.epsilon[i + 1]);
+                let field_change = -(potential[i + 1] - potential[i] - prev_potential[i + 1] + prev_potential[i]) / h[i];
+                epsilon * field_change / time_step * h[i]
+            })
+            .sum::<f64>() / (self.mesh.points[sample_last_idx] - self.mesh.points[0])
+    }
+
+    // residual and jacobian of the coupled drift-diffusion equations
+    // rows per node are (poission, electron continuity, hole continuity), the contacts are fixed
+    fn assemble_coupled(&self, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD, time_term:Option<&TimeTerm>) -> (MatBlockTriDiag, VecD)
+    {
+        let N = self.mesh.len();
+        let sample_last_idx = self.mesh.lastIdx();
+        let h = self.mesh.calcStepVec();
+
+        let mut jacobian = blocktridiag::zeros(N);
+        let mut residual = VecD::zeros(3 * N);
+
+        // poission equation
+        let charge = self.total_charge_qf_vec(fermi_lvl_n, fermi_lvl_p, potential);
+        let charge_derivative = self.total_charge_derivative_pot_qf_vec(fermi_lvl_n, fermi_lvl_p, potential);
+        let [_, interface_derivative_n, interface_derivative_p] = self.interface_charge_derivative_vecs(potential, fermi_lvl_n, fermi_lvl_p).1;
+        let poission_residual = self.poissionProb.residue(potential, &charge);
+        let (subdiag, diag, superdiag) = &self.poissionProb.operator;
+
+        for i in 1..sample_last_idx
+        {
+            let x = self.mesh.points[i];
+
+            residual[3 * i] = poission_residual[i];
+
+            jacobian.0[i - 1][(0, 0)] = subdiag[i - 1];
+            jacobian.1[i][(0, 0)] = diag[i] + charge_derivative[i];
+            jacobian.2[i][(0, 0)] = superdiag[i];
+
+            // charge depends on fermi_lvl / Q like it depends on the potential, as do the ionized donors and acceptors
+            let field = node_field(&self.mesh, potential, i);
+            let dopant_derivative = self.bulk_layers.iter()
+                .map(|layer| layer.dopant_charge_derivative(x, fermi_lvl_n[i], fermi_lvl_p[i], potential[i], field, self.temp).1)
+                .fold([0.0; 4], |acc, d| [acc[0] + d[0], acc[1] + d[1], acc[2] + d[2], acc[3] + d[3]]);
+
+            // the field is the central difference of the potential, so field dependent charge couples to the neighbours
+            let field_coupling = dopant_derivative[3] / (self.mesh.points[i + 1] - self.mesh.points[i - 1]);
+            jacobian.0[i - 1][(0, 0)] += field_coupling;
+            jacobian.2[i][(0, 0)] -= field_coupling;
+
+            let trap_derivative = self.bulk_layers.iter()
+                .map(|layer| layer.trap_charge_derivative(x, fermi_lvl_n[i], fermi_lvl_p[i], potential[i], self.temp).1)
+                .fold([0.0; 3], |acc, d| [acc[0] + d[0], acc[1] + d[1], acc[2] + d[2]]);
+
+            jacobian.1[i][(0, 1)] = dopant_derivative[1] + trap_derivative[1] + interface_derivative_n[i] + self.bulk_layers.iter()
+                .map(|layer| layer.electron_charge_derivative_pot(x, fermi_lvl_n[i], potential[i], self.temp))
+                .sum::<f64>();
+            jacobian.1[i][(0, 2)] = dopant_derivative[2] + trap_derivative[2] + interface_derivative_p[i] + self.bulk_layers.iter()
+                .map(|layer| layer.hole_charge_derivative_pot(x, fermi_lvl_p[i], potential[i], self.temp))
+                .sum::<f64>();
+        }
+
+        // continuity equations, (J[i] - J[i - 1]) / h_avg = q(R - G) for electrons and -q(R - G) for holes
+        for i in 0..sample_last_idx
+        {
+            let x0 = self.mesh.points[i];
+            let x1 = self.mesh.points[i + 1];
+            let layer = match self.bulk_layers.iter().find(|layer| layer.is_inside(0.5 * (x0 + x1))) {
+                Some(layer) => layer,
+                None => continue,
+            };
+
+            let link_pot = (potential[i], potential[i + 1]);
+            let (Jn, dJn) = layer.electron_current_derivative(x0, x1, link_pot, (fermi_lvl_n[i], fermi_lvl_n[i + 1]), self.temp);
+            let (Jp, dJp) = layer.hole_current_derivative(x0, x1, link_pot, (fermi_lvl_p[i], fermi_lvl_p[i + 1]), self.temp);
+
+            // the link leaves node i
+            if i > 0
+            {
+                let h_avg = 0.5 * (h[i - 1] + h[i]);
+
+                residual[3 * i + 1] += Jn / h_avg;
+                residual[3 * i + 2] += Jp / h_avg;
+
+                jacobian.1[i][(1, 0)] += dJn[0] / h_avg;
+                jacobian.1[i][(1, 1)] += dJn[1] / h_avg;
+                jacobian.2[i][(1, 0)] += dJn[2] / h_avg;
+                jacobian.2[i][(1, 1)] += dJn[3] / h_avg;
+
+                jacobian.1[i][(2, 0)] += dJp[0] / h_avg;
+                jacobian.1[i][(2, 2)] += dJp[1] / h_avg;
+                jacobian.2[i][(2, 0)] += dJp[2] / h_avg;
+                jacobian.2[i][(2, 2)] += dJp[3] / h_avg;
+            }
+
+            // the link enters node i + 1
+            if i + 1 < sample_last_idx
+            {
+                let h_avg = 0.5 * (h[i] + h[i + 1]);
+
+                residual[3 * (i + 1) + 1] -= Jn / h_avg;
+                residual[3 * (i + 1) + 2] -= Jp / h_avg;
+
+                jacobian.0[i][(1, 0)] -= dJn[0] / h_avg;
+                jacobian.0[i][(1, 1)] -= dJn[1] / h_avg;
+                jacobian.1[i + 1][(1, 0)] -= dJn[2] / h_avg;
+                jacobian.1[i + 1][(1, 1)] -= dJn[3] / h_avg;
+
+                jacobian.0[i][(2, 0)] -= dJp[0] / h_avg;
+                jacobian.0[i][(2, 2)] -= dJp[1] / h_avg;
+                jacobian.1[i + 1][(2, 0)] -= dJp[2] / h_avg;
+                jacobian.1[i + 1][(2, 2)] -= dJp[3] / h_avg;
+            }
+
+            // impact ionization generation of the link, half of the link volume belongs to each end
+            let (generation, dG) = layer.impact_generation_derivative(x0, x1, link_pot, (fermi_lvl_n[i], fermi_lvl_n[i + 1]), (fermi_lvl_p[i], fermi_lvl_p[i + 1]), self.temp);
+
+            if generation != 0.0
+            {
+                if i > 0
+                {
+                    let weight = constants::Q * h[i] / (h[i - 1] + h[i]);
+
+                    residual[3 * i + 1] += weight * generation;
+                    residual[3 * i + 2] -= weight * generation;
+
+                    for k in 0..3
+                    {
+                        jacobian.1[i][(1, k)] += weight * dG[k];
+                        jacobian.2[i][(1, k)] += weight * dG[3 + k];
+                        jacobian.1[i][(2, k)] -= weight * dG[k];
+                        jacobian.2[i][(2, k)] -= weight * dG[3 + k];
+                    }
+                }
+
+                if i + 1 < sample_last_idx
+                {
+                    let weight = constants::Q * h[i] / (h[i] + h[i + 1]);
+
+                    residual[3 * (i + 1) + 1] += weight * generation;
+                    residual[3 * (i + 1) + 2] -= weight * generation;
+
+                    for k in 0..3
+                    {
+                        jacobian.0[i][(1, k)] += weight * dG[k];
+                        jacobian.1[i + 1][(1, k)] += weight * dG[3 + k];
+                        jacobian.0[i][(2, k)] -= weight * dG[k];
+                        jacobian.1[i + 1][(2, k)] -= weight * dG[3 + k];
+                    }
+                }
+            }
+        }
+
+        // net recombination, electron rows get -qR and hole rows qR
+        for i in 1..sample_last_idx
+        {
+            let (rate, rate_derivative) = self.recombination_derivative(i, potential, fermi_lvl_n, fermi_lvl_p);
+
+            residual[3 * i + 1] -= constants::Q * rate;
+            residual[3 * i + 2] += constants::Q * rate;
+
+            for k in 0..3
+            {
+                jacobian.1[i][(1, k)] -= constants::Q * rate_derivative[k];
+                jacobian.1[i][(2, k)] += constants::Q * rate_derivative[k];
+            }
+        }
+
+        // band to band tunneling generation, the field at node i is the central difference of the potential
+        for i in 1..sample_last_idx
+        {
+            let x = self.mesh.points[i];
+            let width = self.mesh.points[i + 1] - self.mesh.points[i - 1];
+            let field = -(potential[i + 1] - potential[i - 1]) / width;
+
+            for layer in self.bulk_layers.iter()
+            {
+                let (generation, dG) = layer.band_to_band_derivative(x, field, fermi_lvl_n[i], fermi_lvl_p[i], potential[i], self.temp);
+
+                if generation == 0.0
+                {
+                    continue;
+                }
+
+                residual[3 * i + 1] += constants::Q * generation;
+                residual[3 * i + 2] -= constants::Q * generation;
+
+                jacobian.0[i - 1][(1, 0)] += constants::Q * dG[0] / width;
+                jacobian.2[i][(1, 0)] -= constants::Q * dG[0] / width;
+                jacobian.0[i - 1][(2, 0)] -= constants::Q * dG[0] / width;
+                jacobian.2[i][(2, 0)] += constants::Q * dG[0] / width;
+
+                for k in 0..3
+                {
+                    jacobian.1[i][(1, k)] += constants::Q * dG[1 + k];
+                    jacobian.1[i][(2, k)] -= constants::Q * dG[1 + k];
+                }
+            }
+        }
+
+        // carrier density time derivatives, electron rows get -q dn/dt and hole rows q dp/dt
+        if let Some(term) = time_term
+        {
+            let (n, p) = self.carrier_conc_vecs(potential, fermi_lvl_n, fermi_lvl_p);
+            let mass = self.carrier_mass(potential, fermi_lvl_n, fermi_lvl_p);
+
+            for i in 1..sample_last_idx
+            {
+                residual[3 * i + 1] -= constants::Q * (term.coeff * n[i] - term.history_n[i]);
+                residual[3 * i + 2] += constants::Q * (term.coeff * p[i] - term.history_p[i]);
+                jacobian.1[i] += term.coeff * mass[i];
+            }
+        }
+
+        // the contacts are fixed
+        jacobian.1[0] = blocktridiag::Block::identity();
+        jacobian.1[sample_last_idx] = blocktridiag::Block::identity();
+
+        (jacobian, residual)
+    }
+
+    // derivatives of the time derivative terms, (-q dn, q dp) wrt (potential, fermi_lvl / Q) at every node
+    fn carrier_mass(&self, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD) -> Vec<blocktridiag::Block>
+    {
+        self.mesh.points.iter().enumerate()
+            .map(|(i, &x)| {
+                let n_derivative:f64 = self.bulk_layers.iter()
+                    .map(|layer| layer.electron_conc_derivative_pot(x, fermi_lvl_n[i], potential[i], self.temp))
+                    .sum();
+                let p_derivative:f64 = self.bulk_layers.iter()
+                    .map(|layer| layer.hole_conc_derivative_pot(x, fermi_lvl_p[i], potential[i], self.temp))
+                    .sum();
+
+                let mut mass = blocktridiag::Block::zeros();
+                mass[(1, 0)] = -constants::Q * n_derivative;
+                mass[(1, 1)] = -constants::Q * n_derivative;
+                mass[(2, 0)] = constants::Q * p_derivative;
+                mass[(2, 2)] = constants::Q * p_derivative;
+                mass
+            })
+            .collect()
+    }
+
+    // small signal admittance per unit area of the left contact, linearized around the operating point in state
+    // the capacitance is Im(Y) / omega, so the frequencies must be positive
+    pub fn calc_ac_admittance(&self, state:&State, frequencies:&[f64]) -> Vec<Admittance>
+    {
+        if frequencies.iter().any(|&frequency| frequency <= 0.0)
+        {
+            panic!("Error: small signal frequencies must be positive");
+        }
+
+        let N = self.mesh.len();
+        let sample_last_idx = self.mesh.lastIdx();
+
+        let (jacobian, _) = self.assemble_coupled(&state.potential, &state.fermi_lvl_n, &state.fermi_lvl_p, None);
+        let mass = self.carrier_mass(&state.potential, &state.fermi_lvl_n, &state.fermi_lvl_p);
+
+        // current derivatives of the first link, the total current is the same through every link
+        let x0 = self.mesh.points[0];
+        let x1 = self.mesh.points[1];
+        let layer = self.bulk_layers.iter().find(|layer| layer.is_inside(0.5 * (x0 + x1))).expect("No layers initialized! ");
+        let link_pot = (state.potential[0], state.potential[1]);
+        let (_, dJn) = layer.electron_current_derivative(x0, x1, link_pot, (state.fermi_lvl_n[0], state.fermi_lvl_n[1]), self.temp);
+        let (_, dJp) = layer.hole_current_derivative(x0, x1, link_pot, (state.fermi_lvl_p[0], state.fermi_lvl_p[1]), self.temp);
+        let link_epsilon = 0.5 * (self.epsilon[0] + self.epsilon[1]);
+        let h = x1 - x0;
+
+        // a unit voltage perturbation on the left contact, (potential, fermi_lvl_n / Q, fermi_lvl_p / Q)
+        let mut load_vector = na::DVector::<na::Complex<f64>>::zeros(3 * N);
+        load_vector[0] = na::Complex::new(1.0, 0.0);
+        load_vector[1] = na::Complex::new(-1.0, 0.0);
+        load_vector[2] = na::Complex::new(-1.0, 0.0);
+
+        let mut scratch = vec![blocktridiag::BlockOf::zeros(); N];
+
+        frequencies.iter()
+            .map(|&frequency| {
+                let omega = 2.0 * std::f64::consts::PI * frequency;
+                let to_complex = |block:&blocktridiag::Block| block.map(|v| na::Complex::new(v, 0.0));
+
+                let mut system:blocktridiag::MatBlockTriDiagOf<na::Complex<f64>> = (
+                    jacobian.0.iter().map(to_complex).collect(),
+                    jacobian.1.iter().map(to_complex).collect(),
+                    jacobian.2.iter().map(to_complex).collect(),
+                );
+                for i in 1..sample_last_idx
+                {
+                    system.1[i] += mass[i].map(|v| na::Complex::new(0.0, omega * v));
+                }
+
+                let response = blocktridiag::solve(&system, &mut scratch, load_vector.clone());
+
+                let particle_current = 
+                    dJn[0] * response[0] + dJn[1] * response[1] + dJn[2] * response[3] + dJn[3] * response[4] +
+                    dJp[0] * response[0] + dJp[1] * response[2] + dJp[2] * response[3] + dJp[3] * response[5];
+                let displacement_current = na::Complex::new(0.0, omega) * link_epsilon * (response[0] - response[3]) / h;
+
+                let admittance = particle_current + displacement_current;
+
+                Admittance {
+                    frequency,
+                    conductance:admittance.re,
+                    capacitance:admittance.im / omega,
+                }
+            })
+            .collect()
+    }
+
+    // electron initiated ionization integral of a state, avalanche breakdown occurs when it reaches unity
+    // electrons are taken to drift from the lower potential contact to the higher potential contact
+    pub fn ionization_integral(&self, state:&State) -> f64
+    {
+        let h = self.mesh.calcStepVec();
+        let sample_last_idx = self.mesh.lastIdx();
+
+        let coeffs:Vec<(f64, f64)> = (0..sample_last_idx)
+            .map(|i| {
+                let x0 = self.mesh.points[i];
+                let x1 = self.mesh.points[i + 1];
+
+                self.bulk_layers.iter()
+                    .map(|layer| layer.ionization_coeffs(x0, x1, (state.potential[i], state.potential[i + 1])))
+                    .fold((0.0, 0.0), |acc, alpha| (acc.0 + alpha.0, acc.1 + alpha.1))
+            })
+            .collect();
+
+        let links:Vec<usize> = if state.potential[sample_last_idx] >= state.potential[0] {
+            (0..sample_last_idx).collect()
+        } else {
+            (0..sample_last_idx).rev().collect()
+        };
+
+        // I = integral of alpha_n exp(-integral of (alpha_n - alpha_p) up to x) along the electron path
+        let mut integral = 0.0;
+        let mut exponent = 0.0;
+
+        for i in links
+        {
+            let (alpha_n, alpha_p) = coeffs[i];
+
+            integral += alpha_n * f64::exp(-exponent - 0.5 * (alpha_n - alpha_p) * h[i]) * h[i];
+            exponent += (alpha_n - alpha_p) * h[i];
+        }
+
+        integral
+    }
+
+    // sheet charge and carrier sheet densities at each layer boundary, the carriers are integrated over
+    // the nodes within width of the boundary, which collects a 2DEG in the well next to it
+    // the sheet charge holds the polarization, fixed and trapped interface charge
+    pub fn interface_report(&self, state:&State, width:f64) -> Vec<InterfaceReport>
+    {
+        let h = self.mesh.calcStepVec();
+        let trapped_charge = self.interface_charge_derivative_vecs(&state.potential, &state.fermi_lvl_n, &state.fermi_lvl_p).0
+            .component_mul(&self.poissionProb.control_width);
+
+        self.interface_nodes.iter().map(|&interface_idx| {
+            let position = self.mesh.points[interface_idx];
+            let is_inside = |i:usize| (self.mesh.points[i] - position).abs() <= width;
+
+            let (electron_density, hole_density) = (0..self.mesh.lastIdx())
+                .filter(|&i| is_inside(i) && is_inside(i + 1))
+                .fold((0.0, 0.0), |acc, i| (
+                    acc.0 + 0.5 * (state.n[i] + state.n[i + 1]) * h[i],
+                    acc.1 + 0.5 * (state.p[i] + state.p[i + 1]) * h[i]
+                ));
+
+            InterfaceReport {
+                position,
+                sheet_charge:self.sheet_charge[interface_idx] + trapped_charge[interface_idx],
+                electron_density,
+                hole_density,
+            }
+        }).collect()
+    }
+
+    // conduction and valence band offsets Ec(right) - Ec(left) and Ev(right) - Ev(left) at each layer boundary,
+    // the band edges are aligned and strained but without the band gap narrowing of the doping
+    pub fn band_offset_report(&self) -> Vec<BandOffsetReport>
+    {
+        self.interface_nodes.iter().zip(self.bulk_layers.windows(2)).map(|(&interface_idx, layers)| {
+            let position = self.mesh.points[interface_idx];
+            let left = layers[0].bulk_at(position);
+            let right = layers[1].bulk_at(position);
+
+            BandOffsetReport {
+                position,
+                conduction_offset:right.Ec - left.Ec,
+                valence_offset:right.Ev - left.Ev,
+            }
+        }).collect()
+    }
+
+    // band to band tunneling current density q * integral of G dx, with the Kane model this gives an estimate of the
+    // zener current from a poission only (equilibrium) state
+    pub fn band_to_band_current(&self, state:&State) -> f64
+    {
+        let generation = self.band_to_band_vec(&state.potential, &state.fermi_lvl_n, &state.fermi_lvl_p);
+        let h = self.mesh.calcStepVec();
+
+        (0..self.mesh.lastIdx())
+            .map(|i| 0.5 * (generation[i] + generation[i + 1]) * h[i])
+            .sum::<f64>() * constants::Q
+    }
+
+    // initial guess of a biased solve with the ohmic contact values set
+    // continues from the last biased state if there is one, otherwise from the steady state
+    fn biased_initial_guess(&self, applied_voltage:f64) -> (VecD, VecD, VecD)
+    {
+        let initial_state = if self.biased_state.potential.len() == self.mesh.len() { &self.biased_state } else { &self.steady_state };
+
+        let mut potential = initial_state.potential.clone();
+        let mut fermi_lvl_n = initial_state.fermi_lvl_n.clone();
+        let mut fermi_lvl_p = initial_state.fermi_lvl_p.clone();
+
+        // scale the change in bias with the fraction of the previous bias dropped at every node, so that the
+        // quasi-neutral region connected to the left contact follows it, close to equilibrium the fraction of
+        // the built-in potential is used instead
+        let delta_voltage = applied_voltage - initial_state.applied_voltage;
+        let sample_last_idx = self.mesh.lastIdx();
+        let right_fermi_lvl = self.steady_state.fermi_lvl;
+        let prev_drop = -constants::Q * initial_state.applied_voltage;
+        let near_equilibrium = initial_state.applied_voltage.abs() < constants::thermal_pot(self.temp);
+        let (left_potential, right_potential) = (potential[0], potential[sample_last_idx]);
+
+        for i in 0..self.mesh.len()
+        {
+            let (fraction_n, fraction_p) = if near_equilibrium {
+                let fraction = (potential[i] - right_potential) / (left_potential - right_potential);
+                (fraction, fraction)
+            } else {
+                ((fermi_lvl_n[i] - right_fermi_lvl) / prev_drop, (fermi_lvl_p[i] - right_fermi_lvl) / prev_drop)
+            };
+            // no built-in potential to scale with, only the contact moves
+            if !fraction_n.is_finite() || !fraction_p.is_finite()
+            {
+                continue;
+            }
+            let (fraction_n, fraction_p) = (fraction_n.clamp(0.0, 1.0), fraction_p.clamp(0.0, 1.0));
+
+            potential[i] += 0.5 * (fraction_n + fraction_p) * delta_voltage;
+            fermi_lvl_n[i] -= fraction_n * constants::Q * delta_voltage;
+            fermi_lvl_p[i] -= fraction_p * constants::Q * delta_voltage;
+        }
+
+        self.apply_contacts(applied_voltage, &mut potential, &mut fermi_lvl_n, &mut fermi_lvl_p);
+
+        (potential, fermi_lvl_n, fermi_lvl_p)
+    }
+
+    // set the ohmic contact values, applied_voltage is on the left contact and the right contact is grounded
+    fn apply_contacts(&self, applied_voltage:f64, potential:&mut VecD, fermi_lvl_n:&mut VecD, fermi_lvl_p:&mut VecD)
+    {
+        let sample_last_idx = self.mesh.lastIdx();
+
+        let right_fermi_lvl = self.steady_state.fermi_lvl;
+        let left_fermi_lvl = right_fermi_lvl - constants::Q * applied_voltage;
+
+        potential[0] = self.steady_state.potential[0] + applied_voltage;
+        potential[sample_last_idx] = self.steady_state.potential[sample_last_idx];
+        fermi_lvl_n[0] = left_fermi_lvl;
+        fermi_lvl_p[0] = left_fermi_lvl;
+        fermi_lvl_n[sample_last_idx] = right_fermi_lvl;
+        fermi_lvl_p[sample_last_idx] = right_fermi_lvl;
+    }
+
+    fn store_biased_state(&mut self, applied_voltage:f64, potential:VecD, fermi_lvl_n:VecD, fermi_lvl_p:VecD)
+    {
+        self.biased_state = self.make_state(applied_voltage, potential, fermi_lvl_n, fermi_lvl_p);
+    }
+
+    fn make_state(&self, applied_voltage:f64, potential:VecD, fermi_lvl_n:VecD, fermi_lvl_p:VecD) -> State
+    {
+        let mut state = State {
+            potential,
+            fermi_lvl_n,
+            fermi_lvl_p,
+            applied_voltage,
+            fermi_lvl:self.steady_state.fermi_lvl,
+            built_in_potential:self.steady_state.built_in_potential,
+            ..Default::default()
+        };
+        self.fill_state(&mut state);
+        state
+    }
+
+    // newton iterations for the nonlinear poission equation with fixed quasi-fermi levels
+    // the boundary values are taken from the initial potential, returns None if not converged in max_iter
+    fn solve_poission(&self, mut potential:VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD, charge_tol:f64, rel_potential_tol:f64, max_iter:usize) -> Option<(VecD, VecD)>
+    {
+        let sample_last_idx = self.mesh.lastIdx();
+        let left_bc = potential[0];
+        let right_bc = potential[sample_last_idx];
+
+        let mut charge = self.total_charge_qf_vec(fermi_lvl_n, fermi_lvl_p, &potential);
+
+        for i in 0..max_iter {
+            let charge_derivative = self.total_charge_derivative_pot_qf_vec(fermi_lvl_n, fermi_lvl_p, &potential);
+            let charge_derivative_field = self.total_charge_derivative_field_qf_vec(fermi_lvl_n, fermi_lvl_p, &potential);
+
+            // calculate the residual
+            let mut residual = self.poissionProb.residue(&potential, &charge);
+            residual[0] = 0.0;
+            residual[sample_last_idx] = 0.0;
+
+            let poission_mat = self.poissionProb.operator.clone();
+            let mut jacobian:MatTriDiag = (poission_mat.0, &charge_derivative + poission_mat.1, poission_mat.2);
+
+            // field dependent charge couples each node to its neighbours through the central difference field
+            for i in 1..sample_last_idx
+            {
+                let field_coupling = charge_derivative_field[i] / (self.mesh.points[i + 1] - self.mesh.points[i - 1]);
+                jacobian.0[i - 1] += field_coupling;
+                jacobian.2[i] -= field_coupling;
+            }
+
+            jacobian.1[0] = 1.0;
+            jacobian.2[0] = 0.0;
+
+            jacobian.1[sample_last_idx] = 1.0;
+            jacobian.0[sample_last_idx - 1] = 0.0;
+
+            let deltaV = tridiag::solve(&jacobian, &mut self.mesh.zeroVec(), residual);
+
+            let w = 1.5;
+            potential -= w * &deltaV;
+
+            // reinforce the boundary conditions
+            potential[0] = left_bc;
+            potential[sample_last_idx] = right_bc;
+
+            let prev_charge = charge;
+            charge = self.total_charge_qf_vec(fermi_lvl_n, fermi_lvl_p, &potential);
+
+            if (prev_charge - &charge).abs().max() < charge_tol && 
+                deltaV.abs().max() / potential.abs().max() < rel_potential_tol &&
+                i > 1
+            {
+                return Some((potential, charge));
+            }
+        }
+
+        None
+    }
+
+    fn carrier_conc_vecs(&self, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD) -> (VecD, VecD)
+    {
+        let n = self.mesh.makeVecFn( |x, i| 
+            self.bulk_layers.iter()
+            .map(|y| y.electron_conc(x, fermi_lvl_n[i], potential[i], self.temp))
+            .sum()
+        );
+
+        let p = self.mesh.makeVecFn( |x, i| 
+            self.bulk_layers.iter()
+            .map(|y| y.hole_conc(x, fermi_lvl_p[i], potential[i], self.temp))
+            .sum()
+        );
+
+        (n, p)
+    }
+
+    // fill the derived quantities of a state from its potential and quasi-fermi levels
+    fn fill_state(&self, state:&mut State)
+    {
+        state.charge = self.total_charge_qf_vec(&state.fermi_lvl_n, &state.fermi_lvl_p, &state.potential);
+        state.Ec = &self.vacc_Ec - constants::Q * &state.potential;
+        state.Ev = &self.vacc_Ev - constants::Q * &state.potential;
+
+        (state.n, state.p) = self.carrier_conc_vecs(&state.potential, &state.fermi_lvl_n, &state.fermi_lvl_p);
+
+        (state.Jn, state.Jp) = self.current_vec(&state.potential, &state.fermi_lvl_n, &state.fermi_lvl_p);
+        (state.mobility_n, state.mobility_p) = self.mobility_vec(&state.potential);
+        state.recombination = self.recombination_vec(&state.potential, &state.fermi_lvl_n, &state.fermi_lvl_p);
+        state.trap_occupancy = self.bulk_layers.iter()
+            .flat_map(|layer| layer.trap_occupancy_vec(&self.mesh, &state.potential, &state.fermi_lvl_n, &state.fermi_lvl_p, self.temp))
+            .collect();
+        state.generation = self.impact_generation_vec(&state.potential, &state.fermi_lvl_n, &state.fermi_lvl_p) + 
+            self.band_to_band_vec(&state.potential, &state.fermi_lvl_n, &state.fermi_lvl_p);
+        // link currents weighted by the link length, so that refined regions of the mesh do not dominate the mean
+        let h = self.mesh.calcStepVec();
+        state.current = (&state.Jn + &state.Jp).rows(0, self.mesh.lastIdx()).dot(&h.rows(0, self.mesh.lastIdx())) / 
+            (self.mesh.points[self.mesh.lastIdx()] - self.mesh.points[0]);
     }
 
 }
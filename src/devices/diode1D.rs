@@ -1,8 +1,171 @@
 use crate::common::*;
 use super::state::*;
+use super::device::Device;
 
+// terminal current density vs applied voltage, with the full state at every bias
+#[derive(Debug, Default)]
+pub struct IVCurve
+{
+    pub voltage:VecD,
+    pub current:VecD,
+    pub states:Vec<State>,
+}
+
+// small signal capacitance and conductance vs applied voltage at a fixed frequency
+#[derive(Debug, Default)]
+pub struct CVCurve
+{
+    pub frequency:f64,
+    pub voltage:VecD,
+    pub conductance:VecD,
+    pub capacitance:VecD,
+}
+
+impl CVCurve
+{
+    // 1 / C^2 at every bias, linear in voltage for an abrupt one sided junction
+    pub fn mott_schottky(&self) -> VecD
+    {
+        self.capacitance.map(|c| 1.0 / (c * c))
+    }
+}
+
+// two contact diode, the anode is the left contact (x = 0) and the cathode is the right contact
 pub struct Diode
 {
-    
+    pub device:Device,
+    pub charge_tol:f64,
+    pub rel_potential_tol:f64,
+    pub max_iter:usize,
 }
 
+impl Diode
+{
+    pub fn create(device:Device, charge_tol:f64, rel_potential_tol:f64, max_iter:usize) -> Diode
+    {
+        Diode {
+            device,
+            charge_tol,
+            rel_potential_tol,
+            max_iter
+        }
+    }
+
+    // solve a single bias point, starting from the previous one
+    pub fn solve(&mut self, voltage:f64) -> &State
+    {
+        self.device.calc_biased_state(voltage, self.charge_tol, self.rel_potential_tol, self.max_iter);
+        &self.device.biased_state
+    }
+
+    // sweep the anode voltage from start to stop (inclusive) with the given step
+    // every bias point is continued from the previous one, the first point is ramped to
+    // from the last solved bias (or equilibrium) in steps no larger than step
+    pub fn sweep(&mut self, start:f64, stop:f64, step:f64) -> IVCurve
+    {
+        if step == 0.0
+        {
+            panic!("Error: the voltage step of a sweep must be non-zero");
+        }
+
+        let last_voltage = if self.device.biased_state.potential.is_empty() { 0.0 } else { self.device.biased_state.applied_voltage };
+        let ramp_count = ((start - last_voltage) / step).abs().ceil() as usize;
+
+        for i in 1..ramp_count
+        {
+            self.solve(last_voltage + (start - last_voltage) * (i as f64) / (ramp_count as f64));
+        }
+
+        // the tolerance keeps rounding of the step count from adding a point next to stop
+        let point_count = (((stop - start) / step).abs() - 1e-9).ceil() as usize + 1;
+        let signed_step = if stop >= start { step.abs() } else { -step.abs() };
+
+        let mut curve = IVCurve::default();
+        let mut voltage = Vec::with_capacity(point_count);
+        let mut current = Vec::with_capacity(point_count);
+
+        for i in 0..point_count
+        {
+            // the last step is shortened to end the sweep at stop
+            let state = self.solve(if i + 1 == point_count { stop } else { start + signed_step * (i as f64) });
+
+            voltage.push(state.applied_voltage);
+            current.push(state.current);
+            curve.states.push(state.clone());
+        }
+
+        curve.voltage = VecD::from_vec(voltage);
+        curve.current = VecD::from_vec(current);
+
+        curve
+    }
+
+    // small signal admittance at the last solved bias point
+    pub fn admittance(&self, frequencies:&[f64]) -> Vec<Admittance>
+    {
+        self.device.calc_ac_admittance(&self.device.biased_state, frequencies)
+    }
+
+    // sweep the anode voltage like sweep and compute the admittance at every bias point
+    pub fn cv_sweep(&mut self, start:f64, stop:f64, step:f64, frequency:f64) -> CVCurve
+    {
+        let iv_curve = self.sweep(start, stop, step);
+
+        let admittances:Vec<Admittance> = iv_curve.states.iter()
+            .map(|state| self.device.calc_ac_admittance(state, &[frequency])[0])
+            .collect();
+
+        CVCurve {
+            frequency,
+            voltage:iv_curve.voltage,
+            conductance:VecD::from_iterator(admittances.len(), admittances.iter().map(|y| y.conductance)),
+            capacitance:VecD::from_iterator(admittances.len(), admittances.iter().map(|y| y.capacitance)),
+        }
+    }
+
+    // avalanche breakdown voltage, ramps the anode voltage from the last solved bias (or equilibrium) towards stop
+    // until the ionization integral reaches unity, the current density exceeds current_limit or the solver diverges
+    // returns None if the device does not break down before stop
+    pub fn breakdown_voltage(&mut self, stop:f64, step:f64, current_limit:f64) -> Option<f64>
+    {
+        if step == 0.0
+        {
+            panic!("Error: the voltage step of a sweep must be non-zero");
+        }
+
+        let solved = !self.device.biased_state.potential.is_empty();
+        let start = if solved { self.device.biased_state.applied_voltage } else { 0.0 };
+        let signed_step = if stop >= start { step.abs() } else { -step.abs() };
+        let point_count = ((stop - start) / step).abs().ceil() as usize;
+
+        let mut prev_voltage = start;
+        let mut prev_integral = if solved { self.device.ionization_integral(&self.device.biased_state) } else { 0.0 };
+
+        for i in 1..=point_count
+        {
+            let voltage = if i == point_count { stop } else { start + signed_step * (i as f64) };
+
+            // the current diverges at breakdown, newton fails to converge close to it
+            if !self.device.try_calc_biased_state(voltage, self.charge_tol, self.rel_potential_tol, self.max_iter)
+            {
+                return Some(prev_voltage);
+            }
+
+            let integral = self.device.ionization_integral(&self.device.biased_state);
+
+            if integral >= 1.0
+            {
+                return Some(prev_voltage + (voltage - prev_voltage) * (1.0 - prev_integral) / (integral - prev_integral));
+            }
+            if self.device.biased_state.current.abs() > current_limit
+            {
+                return Some(voltage);
+            }
+
+            prev_voltage = voltage;
+            prev_integral = integral;
+        }
+
+        None
+    }
+}
@@ -0,0 +1,115 @@
+use crate::common::*;
+use crate::semiconductor::Types;
+use constants::Q;
+
+// energy points of the Dit(E) integration
+const ENERGY_SAMPLES:usize = 200;
+
+// interface trap density Dit(E) in J^-1 m^-2, sampled at energies measured from the midgap at the interface
+// and linearly interpolated between the samples, zero outside them
+// donor like states are positive when empty and acceptor like states negative when occupied, the states follow
+// the electron quasi-fermi level above the midgap and the hole one below
+#[derive(Debug)]
+pub struct InterfaceTraps
+{
+    pub sampled_density:Vec<f64>,
+    pub sampled_energy:Vec<f64>,
+    pub trap_type:Types,
+}
+
+// boundary between consecutive layers with a fixed sheet charge (C/m^2) and interface traps
+#[derive(Debug, Default)]
+pub struct Interface
+{
+    pub fixed_charge:f64,
+    pub traps:Vec<InterfaceTraps>,
+}
+
+impl InterfaceTraps
+{
+    pub fn create(sampled_density:Vec<f64>, sampled_energy:Vec<f64>, trap_type:Types) -> InterfaceTraps
+    {
+        if sampled_density.len() != sampled_energy.len() || sampled_energy.is_empty()
+        {
+            panic!("Error: interface trap density and energy samples must have the same non zero length");
+        }
+
+        InterfaceTraps {
+            sampled_density,
+            sampled_energy,
+            trap_type
+        }
+    }
+
+    // uniform density over [midgap + lower_energy, midgap + upper_energy]
+    pub fn create_uniform(density:f64, lower_energy:f64, upper_energy:f64, trap_type:Types) -> InterfaceTraps
+    {
+        InterfaceTraps::create(vec![density, density], vec![lower_energy, upper_energy], trap_type)
+    }
+
+    // sheet charge for the midgap energy at the interface and its derivatives wrt [potential, fermi_lvl_n / Q, fermi_lvl_p / Q],
+    // the midgap includes the -Q potential of the band edges
+    pub fn sheet_charge_derivative(&self, midgap:f64, fermi_lvl_n:f64, fermi_lvl_p:f64, temp:f64) -> (f64, [f64; 3])
+    {
+        let thermal_energy = constants::K * temp;
+        let lower_energy = self.sampled_energy[0];
+        let upper_energy = *self.sampled_energy.last().unwrap();
+
+        if upper_energy <= lower_energy
+        {
+            return (0.0, [0.0; 3]);
+        }
+
+        let step = (upper_energy - lower_energy) / (ENERGY_SAMPLES - 1) as f64;
+        let mut charge = 0.0;
+        let mut derivative = [0.0; 3];
+
+        for k in 0..ENERGY_SAMPLES
+        {
+            let energy = lower_energy + step * k as f64;
+            let weight = if k == 0 || k == ENERGY_SAMPLES - 1 { 0.5 * step } else { step };
+            let density = weight * interp::linear1D(energy, &self.sampled_density, &self.sampled_energy);
+
+            let (fermi_lvl, idx) = if energy >= 0.0 { (fermi_lvl_n, 1) } else { (fermi_lvl_p, 2) };
+
+            // occupancy 1 / (1 + exp((E - Ef) / kT)), its derivative wrt the potential and Ef / Q is f (1 - f) Q / kT
+            let exponent = (midgap + energy - fermi_lvl) / thermal_energy;
+            let occupancy = 1.0 / (1.0 + exponent.exp());
+            let occupancy_derivative = occupancy * (1.0 - occupancy) * Q / thermal_energy;
+
+            charge += match self.trap_type {
+                Types::Donor => Q * density * (1.0 - occupancy),
+                Types::Acceptor => -Q * density * occupancy,
+            };
+
+            derivative[0] -= Q * density * occupancy_derivative;
+            derivative[idx] -= Q * density * occupancy_derivative;
+        }
+
+        (charge, derivative)
+    }
+}
+
+impl Interface
+{
+    pub fn create(fixed_charge:f64) -> Interface
+    {
+        Interface {
+            fixed_charge,
+            traps:Vec::new()
+        }
+    }
+
+    pub fn push_traps(&mut self, traps:InterfaceTraps)
+    {
+        self.traps.push(traps);
+    }
+
+    // trapped sheet charge and its derivatives wrt [potential, fermi_lvl_n / Q, fermi_lvl_p / Q], without the fixed charge
+    pub fn trap_charge_derivative(&self, midgap:f64, fermi_lvl_n:f64, fermi_lvl_p:f64, temp:f64) -> (f64, [f64; 3])
+    {
+        self.traps.iter()
+            .map(|traps| traps.sheet_charge_derivative(midgap, fermi_lvl_n, fermi_lvl_p, temp))
+            .fold((0.0, [0.0; 3]), |acc, c| (acc.0 + c.0, [acc.1[0] + c.1[0], acc.1[1] + c.1[1], acc.1[2] + c.1[2]]))
+    }
+}
@@ -2,4 +2,5 @@
 pub mod device;
 pub mod state;
 pub mod diode1D;
+pub mod interface;
 
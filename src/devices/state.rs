@@ -1,6 +1,6 @@
 use crate::common::*;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct State
 {
     pub potential:VecD,
@@ -11,6 +11,53 @@ pub struct State
     pub built_in_potential:f64,
     pub Ec:VecD,
     pub Ev:VecD,
+
+    // non-equilibrium quantities
+    pub applied_voltage:f64,
+    pub fermi_lvl_n:VecD,
+    pub fermi_lvl_p:VecD,
+    // link current densities, J[i] flows from mesh.points[i] to mesh.points[i + 1]
+    pub Jn:VecD,
+    pub Jp:VecD,
+    // net recombination rate per node
+    pub recombination:VecD,
+    // mean number of trapped electrons per trap at each node, one vector per trap in layer order
+    pub trap_occupancy:Vec<VecD>,
+    // generation rate per node from impact ionization and band to band tunneling
+    pub generation:VecD,
+    // electron and hole mobility per node
+    pub mobility_n:VecD,
+    pub mobility_p:VecD,
+    // terminal current density
+    pub current:f64,
+}
+
+// charge at a layer boundary, sheet_charge in C/m^2 and the carrier sheet densities around it in m^-2
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InterfaceReport
+{
+    pub position:f64,
+    pub sheet_charge:f64,
+    pub electron_density:f64,
+    pub hole_density:f64,
+}
+
+// band offsets at a layer boundary in J, positive when the band edge rises from left to right
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BandOffsetReport
+{
+    pub position:f64,
+    pub conduction_offset:f64,
+    pub valence_offset:f64,
+}
+
+// small signal admittance per unit area, Y = conductance + i * 2 * pi * frequency * capacitance
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Admittance
+{
+    pub frequency:f64,
+    pub conductance:f64,
+    pub capacitance:f64,
 }
 
 pub struct TransientFrame
@@ -0,0 +1,161 @@
+pub type BlockOf<T> = na::Matrix3<T>;
+pub type Block = BlockOf<f64>;
+
+// block tridiagonal matrix with 3x3 blocks, (subdiag, diag, superdiag) like MatTriDiag
+// vectors are interleaved, b[3 * i + k] is the k-th component at node i
+pub type MatBlockTriDiagOf<T> = (Vec<BlockOf<T>>, Vec<BlockOf<T>>, Vec<BlockOf<T>>);
+pub type MatBlockTriDiag = MatBlockTriDiagOf<f64>;
+
+pub fn zeros<T:na::ComplexField + Copy>(N:usize) -> MatBlockTriDiagOf<T>
+{
+    (vec![BlockOf::zeros(); N], vec![BlockOf::zeros(); N], vec![BlockOf::zeros(); N])
+}
+
+// convert the block tridiagonal repr to matrix repr
+pub fn as_matrix<T:na::ComplexField + Copy>(val:&MatBlockTriDiagOf<T>) -> na::DMatrix<T>
+{
+    let N = val.1.len();
+    let mut mat = na::DMatrix::zeros(3 * N, 3 * N);
+
+    for i in 0..N
+    {
+        mat.fixed_view_mut::<3, 3>(3 * i, 3 * i).copy_from(&val.1[i]);
+    }
+    for i in 0..(N-1)
+    {
+        mat.fixed_view_mut::<3, 3>(3 * i, 3 * (i + 1)).copy_from(&val.2[i]);
+    }
+    for i in 1..N
+    {
+        mat.fixed_view_mut::<3, 3>(3 * i, 3 * (i - 1)).copy_from(&val.0[i - 1]);
+    }
+    return mat;
+}
+
+fn block_of<T:na::ComplexField + Copy>(b:&na::DVector<T>, i:usize) -> na::Vector3<T>
+{
+    na::Vector3::new(b[3 * i], b[3 * i + 1], b[3 * i + 2])
+}
+
+fn set_block<T:na::ComplexField + Copy>(b:&mut na::DVector<T>, i:usize, value:&na::Vector3<T>)
+{
+    b.fixed_rows_mut::<3>(3 * i).copy_from(value);
+}
+
+pub fn apply<T:na::ComplexField + Copy>(A:&MatBlockTriDiagOf<T>, b:&na::DVector<T>) -> na::DVector<T>
+{
+    let (subdiag, diag, superdiag) = A;
+    let N = diag.len();
+    // checks
+    if subdiag.len() != N || superdiag.len() != N || b.len() != 3 * N
+    {
+        panic!("input dimension mismatch while applying block matrix")
+    }
+
+    let mut x = na::DVector::zeros(3 * N);
+
+    // first element
+    set_block(&mut x, 0, &(diag[0] * block_of(b, 0) + superdiag[0] * block_of(b, 1)));
+    for i in 1..(N-1)
+    {
+        set_block(&mut x, i, &(subdiag[i - 1] * block_of(b, i - 1) + diag[i] * block_of(b, i) + superdiag[i] * block_of(b, i + 1)));
+    }
+    set_block(&mut x, N - 1, &(subdiag[N - 2] * block_of(b, N - 2) + diag[N - 1] * block_of(b, N - 1)));
+
+    return x;
+}
+
+// block thomas algorithm, the diagonal blocks are factorized with partial pivoting
+pub fn solve<T:na::ComplexField + Copy>(A:&MatBlockTriDiagOf<T>, scratch:&mut Vec<BlockOf<T>>, b:na::DVector<T>) -> na::DVector<T>
+{
+    try_solve(A, scratch, b).expect("singular block in block TDMA")
+}
+
+// like solve, returns None if a block is singular
+pub fn try_solve<T:na::ComplexField + Copy>(A:&MatBlockTriDiagOf<T>, scratch:&mut Vec<BlockOf<T>>, mut b:na::DVector<T>) -> Option<na::DVector<T>>
+{
+    let (subdiag, diag, superdiag) = A;
+    let N = diag.len();
+    // checks
+    if subdiag.len() != N || superdiag.len() != N || scratch.len() != N || b.len() != 3 * N
+    {
+        panic!("input dimension mismatch in block TDMA")
+    }
+
+    let lu = diag[0].lu();
+    scratch[0] = lu.solve(&superdiag[0])?;
+    let value = lu.solve(&block_of(&b, 0))?;
+    set_block(&mut b, 0, &value);
+
+    /* loop from 1 to X - 1 inclusive */
+    for ix in 1..=N-1
+    {
+        let lu = (diag[ix] - subdiag[ix - 1] * scratch[ix - 1]).lu();
+
+        if ix < N - 1
+        {
+            scratch[ix] = lu.solve(&superdiag[ix])?;
+        }
+        let value = lu.solve(&(block_of(&b, ix) - subdiag[ix - 1] * block_of(&b, ix - 1)))?;
+        set_block(&mut b, ix, &value);
+    }
+
+    /* loop from X - 2 to 0 inclusive */
+    for ix in (0..=(N-2)).rev()
+    {
+        let value = block_of(&b, ix) - scratch[ix] * block_of(&b, ix + 1);
+        set_block(&mut b, ix, &value);
+    }
+
+    Some(b)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // diagonally dominant system with coupled components, entry(i, r, c) gives the off diagonal values
+    fn test_system<T:na::ComplexField + Copy>(N:usize, entry:impl Fn(usize, usize, usize) -> T) -> MatBlockTriDiagOf<T>
+    {
+        let mut A = zeros(N);
+
+        for i in 0..N
+        {
+            A.0[i] = BlockOf::from_fn(|r, c| entry(i, r, c));
+            A.2[i] = BlockOf::from_fn(|r, c| entry(i + 1, c, r));
+            A.1[i] = BlockOf::from_fn(|r, c| if r == c { T::from_f64(10.0).unwrap() } else { entry(i + 2, r, c) });
+        }
+
+        A
+    }
+
+    fn check_solution<T:na::ComplexField<RealField = f64> + Copy>(A:&MatBlockTriDiagOf<T>, b:&na::DVector<T>)
+    {
+        let mut scratch = vec![BlockOf::zeros(); A.1.len()];
+        let x = solve(A, &mut scratch, b.clone());
+
+        assert!((apply(A, &x) - b).norm() < 1e-12 * b.norm());
+        assert!((as_matrix(A) * &x - b).norm() < 1e-12 * b.norm());
+    }
+
+    #[test]
+    fn solve_inverts_apply()
+    {
+        let N = 5;
+        let A = test_system(N, |i, r, c| 0.1 * (i as f64 + 1.0) - 0.2 * (r as f64) + 0.1 * (c as f64));
+        let b = na::DVector::from_fn(3 * N, |k, _| (k as f64 + 1.0).sin());
+
+        check_solution(&A, &b);
+    }
+
+    #[test]
+    fn solve_inverts_apply_complex()
+    {
+        let N = 5;
+        let A = test_system(N, |i, r, c| na::Complex::new(0.1 * (i as f64 + 1.0) - 0.2 * (r as f64), 0.1 * (c as f64) - 0.1));
+        let b = na::DVector::from_fn(3 * N, |k, _| na::Complex::new((k as f64 + 1.0).sin(), (k as f64).cos()));
+
+        check_solution(&A, &b);
+    }
+}
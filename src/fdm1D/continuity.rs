@@ -0,0 +1,109 @@
+// Solve carrier continuity equations
+// Scharfetter-Gummel discretization on the mesh links
+#![allow(non_snake_case)]
+
+use crate::common::*;
+use super::mesh::Mesh;
+use super::tridiag;
+use tridiag::MatTriDiag;
+
+// bernoulli function B(x) = x / (exp(x) - 1)
+pub fn bernoulli(x:f64) -> f64
+{
+    if x.abs() < 1e-10
+    {
+        return 1.0 - 0.5 * x;
+    }
+
+    x / x.exp_m1()
+}
+
+// Scharfetter-Gummel flux (per unit diffusivity) through a link of length h
+// u0, u1 are the normalized potential energies seen by the carrier at the link ends
+// c0, c1 are the carrier concentrations at the link ends
+pub fn sg_flux(h:f64, u0:f64, u1:f64, c0:f64, c1:f64) -> f64
+{
+    (c1 * bernoulli(u0 - u1) - c0 * bernoulli(u1 - u0)) / h
+}
+
+// derivative of the bernoulli function
+pub fn bernoulli_derivative(x:f64) -> f64
+{
+    if x.abs() < 1e-5
+    {
+        return -0.5 + x / 6.0;
+    }
+    if x > 700.0
+    {
+        return 0.0;
+    }
+
+    let expm1 = x.exp_m1();
+    (expm1 - x * (expm1 + 1.0)) / (expm1 * expm1)
+}
+
+// derivatives of sg_flux wrt (u0, u1, c0, c1)
+pub fn sg_flux_derivative(h:f64, u0:f64, u1:f64, c0:f64, c1:f64) -> [f64; 4]
+{
+    let du0 = (c1 * bernoulli_derivative(u0 - u1) + c0 * bernoulli_derivative(u1 - u0)) / h;
+
+    [du0, -du0, -bernoulli(u1 - u0) / h, bernoulli(u0 - u1) / h]
+}
+
+// Scharfetter-Gummel flux in slotboom variables, flux = conductance * (phi1 - phi0)
+// where c = phi * exp(-u), so it is linear in the slotboom variable
+pub fn sg_conductance(h:f64, u0:f64, u1:f64) -> f64
+{
+    f64::exp(-u0) * bernoulli(u1 - u0) / h
+}
+
+// continuity equation in slotboom variables
+// (J[i] - J[i - 1]) / h_avg = linear_source[i] * phi[i] + source[i], with J[i] = conductance[i] * (phi[i + 1] - phi[i])
+#[derive(Default)]
+pub struct ContinuityProblem
+{
+    pub operator: MatTriDiag,
+    pub scratch: VecD,
+}
+
+impl ContinuityProblem
+{
+    // make a continuity problem from a mesh, the link conductances and the linearized part of the source
+    pub fn create(mesh:&Mesh, conductance:&VecD, linear_source:&VecD) -> ContinuityProblem
+    {
+        let mut subdiag = mesh.zeroVec();
+        let mut diag = mesh.zeroVec();
+        let mut superdiag = mesh.zeroVec();
+
+        let h = mesh.calcStepVec();
+
+        for i in 1..mesh.lastIdx()
+        {
+            let h_avg = 0.5 * (h[i - 1] + h[i]);
+
+            diag[i] = - (conductance[i] + conductance[i - 1]) / h_avg - linear_source[i];
+            superdiag[i] = conductance[i] / h_avg;
+            subdiag[i - 1] = conductance[i - 1] / h_avg;
+        }
+
+        // set the first and last element
+        diag[0] = 1.0;
+        diag[mesh.lastIdx()] = 1.0;
+
+        ContinuityProblem{
+            operator: (subdiag, diag, superdiag),
+            scratch: mesh.zeroVec(),
+        }
+    }
+
+    pub fn solve(&mut self, source:&VecD, left_bc:f64, right_bc:f64) -> VecD
+    {
+        let mut load_vector = source.clone();
+
+        // apply boundary conditions
+        load_vector[0] = left_bc;
+        load_vector[source.len() - 1] = right_bc;
+
+        return tridiag::solve(&self.operator, &mut self.scratch, load_vector);
+    }
+}
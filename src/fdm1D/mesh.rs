@@ -1,10 +1,104 @@
 use crate::common::*;
 
+// fixed point iterations of the step that fits the target step over its own link
+const STEP_ITERATIONS:usize = 8;
+// distance, relative to the local step, within which a position is taken to be at a mesh point
+const POINT_TOLERANCE:f64 = 1e-6;
+
 pub struct Mesh
 {
     pub points: Vec<f64>,
 }
 
+// region [begin, end] meshed with steps of at most step
+#[derive(Debug, Clone, Copy)]
+pub struct RefinementBox
+{
+    pub begin:f64,
+    pub end:f64,
+    pub step:f64,
+}
+
+// non uniform spacing of a layer, positions are those of the device
+// the step is max_step away from the ends of the layer and the refinement boxes, interface_step at the layer
+// ends and the box step inside the boxes, it grows geometrically by growth_ratio in between and is never below min_step
+#[derive(Debug, Clone)]
+pub struct GradedMesh
+{
+    pub min_step:f64,
+    pub max_step:f64,
+    pub growth_ratio:f64,
+    pub interface_step:Option<f64>,
+    pub refinement_boxes:Vec<RefinementBox>,
+}
+
+// meshing of a layer
+#[derive(Debug, Clone)]
+pub enum MeshSpec
+{
+    // equal steps, the number of links in the layer
+    Uniform(u32),
+    Graded(GradedMesh),
+}
+
+impl GradedMesh
+{
+    pub fn create(min_step:f64, max_step:f64, growth_ratio:f64) -> GradedMesh
+    {
+        if min_step <= 0.0 || max_step < min_step
+        {
+            panic!("Error: mesh steps must satisfy 0 < min_step <= max_step");
+        }
+        if growth_ratio < 1.0
+        {
+            panic!("Error: mesh growth ratio must be at least 1");
+        }
+
+        GradedMesh {
+            min_step,
+            max_step,
+            growth_ratio,
+            interface_step:None,
+            refinement_boxes:Vec::new()
+        }
+    }
+
+    // grade the steps down to interface_step at both ends of the layer
+    pub fn grade_to_interfaces(&mut self, interface_step:f64)
+    {
+        self.interface_step = Some(interface_step);
+    }
+
+    // refine [center - half_width, center + half_width] to step
+    pub fn push_refinement_box(&mut self, center:f64, half_width:f64, step:f64)
+    {
+        self.refinement_boxes.push(RefinementBox { begin:center - half_width, end:center + half_width, step });
+    }
+
+    // largest step allowed over all of [x0, x1] in a layer [begin, end], the step grows by (growth_ratio - 1) per
+    // unit distance from each refined region, so that consecutive steps grow by growth_ratio
+    pub fn step_over(&self, x0:f64, x1:f64, begin:f64, end:f64) -> f64
+    {
+        let growth = self.growth_ratio - 1.0;
+        let distance = |region_begin:f64, region_end:f64| (region_begin - x1).max(x0 - region_end).max(0.0);
+
+        let mut step = self.max_step;
+
+        if let Some(interface_step) = self.interface_step
+        {
+            step = step.min(interface_step + growth * distance(begin, begin));
+            step = step.min(interface_step + growth * distance(end, end));
+        }
+
+        for refinement in &self.refinement_boxes
+        {
+            step = step.min(refinement.step + growth * distance(refinement.begin, refinement.end));
+        }
+
+        step.max(self.min_step)
+    }
+}
+
 impl Mesh
 {
     pub fn len(&self) -> usize { self.points.len() }
@@ -45,10 +139,353 @@ impl Mesh
         }
     }
 
+    // equal steps over [begin, end], links + 1 points with the last one placed at end exactly
+    pub fn uniform(begin:f64, end:f64, links:u32) -> Mesh
+    {
+        if links == 0
+        {
+            panic!("Error: a mesh needs at least one link");
+        }
+        if end <= begin
+        {
+            panic!("Error: a mesh needs end > begin");
+        }
+
+        Mesh::create(
+            (0..=links).map(|i| if i == links { end } else { begin + f64::from(i) * ((end - begin) / f64::from(links)) })
+            .collect()
+        )
+    }
+
+    // graded steps over [begin, end], each step fits the target of spec over its link, the last step overshoots
+    // end and the overshoot is taken from the steps in proportion to their room above min_step, or when that is too
+    // small the last step is dropped and the gap is spread over the room below max_step
+    pub fn graded(begin:f64, end:f64, spec:&GradedMesh) -> Mesh
+    {
+        if end <= begin
+        {
+            panic!("Error: a mesh needs end > begin");
+        }
+
+        let mut steps = Vec::new();
+        let mut x = begin;
+
+        // steps ending within the rounding tolerance of end close the layer
+        while x < end - POINT_TOLERANCE * spec.min_step
+        {
+            let mut step = spec.step_over(x, x, begin, end);
+            for _ in 0..STEP_ITERATIONS
+            {
+                step = spec.step_over(x, x + step, begin, end);
+            }
+
+            steps.push(step);
+            x += step;
+        }
+
+        let overshoot = x - end;
+        let shrink_room:f64 = steps.iter().map(|step| step - spec.min_step).sum();
+
+        if overshoot <= POINT_TOLERANCE * spec.min_step
+        {
+            // the steps close the layer up to rounding, the last point is placed at end
+        }
+        else if overshoot <= shrink_room
+        {
+            for step in steps.iter_mut()
+            {
+                *step -= overshoot * (*step - spec.min_step) / shrink_room;
+            }
+        }
+        else
+        {
+            let last_step = steps.pop().unwrap();
+            let gap = last_step - overshoot;
+            let grow_room:f64 = steps.iter().map(|step| spec.max_step - step).sum();
+
+            if gap > grow_room
+            {
+                panic!("Error: the layer [{:e}, {:e}] can not be meshed with steps between min_step and max_step", begin, end);
+            }
+
+            for step in steps.iter_mut()
+            {
+                *step += gap * (spec.max_step - *step) / grow_room;
+            }
+        }
+
+        let mut points = vec![begin];
+        let mut x = begin;
+
+        for (i, step) in steps.iter().enumerate()
+        {
+            x += step;
+            points.push(if i + 1 == steps.len() { end } else { x });
+        }
+
+        Mesh::create(points)
+    }
+
+    pub fn from_spec(begin:f64, end:f64, spec:&MeshSpec) -> Mesh
+    {
+        match spec {
+            MeshSpec::Uniform(links) => Mesh::uniform(begin, end, *links),
+            MeshSpec::Graded(graded) => Mesh::graded(begin, end, graded),
+        }
+    }
+
     pub fn extend(&mut self, points:Vec<f64>)
     {
         self.points.extend(points);
     }
+
+    // adapted mesh, links with an indicator above 1 are halved and a node is dropped when both of its links are
+    // below coarsen_threshold, at most every other node in one pass so that the mesh coarsens gradually
+    // steps stay above min_step when refining and below max_step when coarsening, the ends and the fixed_nodes are kept
+    pub fn adapt(&self, link_indicator:&VecD, coarsen_threshold:f64, min_step:f64, max_step:f64, fixed_nodes:&[usize]) -> Mesh
+    {
+        let mut points = vec![self.points[0]];
+        let mut removed_prev = false;
+
+        for i in 0..self.lastIdx()
+        {
+            let (x0, x1) = (self.points[i], self.points[i + 1]);
+
+            if link_indicator[i] > 1.0 && 0.5 * (x1 - x0) >= min_step
+            {
+                points.push(0.5 * (x0 + x1));
+            }
+
+            let removable = i + 1 < self.lastIdx()
+                && !removed_prev
+                && link_indicator[i] < coarsen_threshold
+                && link_indicator[i + 1] < coarsen_threshold
+                && self.points[i + 2] - points.last().unwrap() <= max_step
+                && !fixed_nodes.contains(&(i + 1));
+
+            if !removable
+            {
+                points.push(x1);
+            }
+            removed_prev = removable;
+        }
+
+        Mesh::create(points)
+    }
+
+    // index of the point at x, positions within POINT_TOLERANCE of the local step of a point are taken to be at it
+    pub fn find_point(&self, x:f64) -> Option<usize>
+    {
+        let next = self.points.partition_point(|&point| point < x).min(self.lastIdx());
+        let idx = if next > 0 && x - self.points[next - 1] < self.points[next] - x { next - 1 } else { next };
+
+        let local_step = [idx.checked_sub(1), Some(idx + 1)].iter()
+            .filter_map(|&neighbour| neighbour.filter(|&k| k < self.len()))
+            .map(|k| (self.points[k] - self.points[idx]).abs())
+            .fold(f64::INFINITY, f64::min);
+
+        if (self.points[idx] - x).abs() <= POINT_TOLERANCE * local_step { Some(idx) } else { None }
+    }
+
+    // linear interpolation of values at the points of this mesh to the points of mesh, constant beyond the ends
+    pub fn interpolate(&self, values:&VecD, mesh:&Mesh) -> VecD
+    {
+        let mut result = mesh.zeroVec();
+        let mut k = 0;
+
+        for (i, &x) in mesh.points.iter().enumerate()
+        {
+            while k + 1 < self.lastIdx() && self.points[k + 1] < x
+            {
+                k += 1;
+            }
+
+            result[i] = if self.len() == 1 || x <= self.points[0] {
+                values[0]
+            } else if x >= self.points[self.lastIdx()] {
+                values[self.lastIdx()]
+            } else {
+                let t = (x - self.points[k]) / (self.points[k + 1] - self.points[k]);
+                values[k] + t * (values[k + 1] - values[k])
+            };
+        }
+
+        result
+    }
 }
 
 
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // ends at begin and end exactly, steps within [min_step, max_step] that change at most by growth_ratio
+    fn check_graded(mesh:&Mesh, begin:f64, end:f64, spec:&GradedMesh)
+    {
+        let steps = mesh.calcStepVec();
+        let steps = &steps.as_slice()[..mesh.lastIdx()];
+        let tolerance = 1e-9;
+
+        assert_eq!(mesh.points[0], begin);
+        assert_eq!(mesh.points[mesh.lastIdx()], end);
+        assert!(steps.iter().all(|&step| step >= spec.min_step * (1.0 - tolerance) && step <= spec.max_step * (1.0 + tolerance)), "{:?}", steps);
+        assert!(steps.windows(2).all(|pair| pair[1].max(pair[0]) <= spec.growth_ratio * pair[1].min(pair[0]) * (1.0 + tolerance)), "{:?}", steps);
+    }
+
+    #[test]
+    fn step_over_follows_the_refined_regions()
+    {
+        let mut spec = GradedMesh::create(0.01, 0.5, 1.5);
+        assert_eq!(spec.step_over(0.0, 0.1, 0.0, 1.0), 0.5);
+
+        spec.grade_to_interfaces(0.05);
+        assert_eq!(spec.step_over(0.0, 0.1, 0.0, 1.0), 0.05);
+        assert_eq!(spec.step_over(0.9, 1.0, 0.0, 1.0), 0.05);
+        assert!((spec.step_over(0.2, 0.3, 0.0, 1.0) - (0.05 + 0.5 * 0.2)).abs() < 1e-12);
+        assert_eq!(spec.step_over(4.0, 5.0, 0.0, 10.0), 0.5);
+
+        spec.push_refinement_box(0.5, 0.1, 0.001);
+        assert_eq!(spec.step_over(0.45, 0.46, 0.0, 1.0), 0.01);
+        assert!((spec.step_over(0.7, 0.8, 0.0, 1.0) - (0.001 + 0.5 * 0.1)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn graded_closes_an_exact_fit()
+    {
+        let spec = GradedMesh::create(0.1, 0.2, 2.0);
+        let mesh = Mesh::graded(0.0, 1.0, &spec);
+
+        check_graded(&mesh, 0.0, 1.0, &spec);
+        assert_eq!(mesh.len(), 6);
+    }
+
+    #[test]
+    fn graded_shrinks_the_steps_to_close_the_layer()
+    {
+        // four max steps overshoot the end by 0.2, which is taken evenly from the steps
+        let spec = GradedMesh::create(0.1, 0.3, 1.0);
+        let mesh = Mesh::graded(0.0, 1.0, &spec);
+
+        check_graded(&mesh, 0.0, 1.0, &spec);
+        assert_eq!(mesh.len(), 5);
+        assert!(mesh.calcStepVec().as_slice()[..4].iter().all(|&step| (step - 0.25).abs() < 1e-12));
+    }
+
+    #[test]
+    fn graded_drops_the_last_step_when_shrinking_does_not_fit()
+    {
+        // the last interface step overshoots by more than the room above min_step, the other two steps grow
+        let mut spec = GradedMesh::create(0.1, 0.2, 1.5);
+        spec.grade_to_interfaces(0.1);
+        let mesh = Mesh::graded(0.0, 0.25, &spec);
+
+        check_graded(&mesh, 0.0, 0.25, &spec);
+        assert_eq!(mesh.len(), 3);
+    }
+
+    #[test]
+    fn graded_refines_interfaces_and_boxes()
+    {
+        let mut spec = GradedMesh::create(1e-10, 5e-9, 1.2);
+        spec.grade_to_interfaces(2e-10);
+        spec.push_refinement_box(50e-9, 5e-9, 1e-10);
+        let mesh = Mesh::graded(10e-9, 110e-9, &spec);
+
+        check_graded(&mesh, 10e-9, 110e-9, &spec);
+        let steps = mesh.calcStepVec();
+        assert!(steps[0] < 2.5e-10 && steps[mesh.lastIdx() - 1] < 2.5e-10);
+        assert!(mesh.points.windows(2).any(|pair| pair[0] >= 45e-9 && pair[1] <= 55e-9 && pair[1] - pair[0] < 1.5e-10));
+    }
+
+    #[test]
+    #[should_panic(expected = "can not be meshed with steps between min_step and max_step")]
+    fn graded_rejects_a_layer_without_fitting_steps()
+    {
+        let mut spec = GradedMesh::create(0.19, 0.2, 2.0);
+        spec.grade_to_interfaces(0.19);
+        Mesh::graded(0.0, 1.0, &spec);
+    }
+
+    #[test]
+    #[should_panic(expected = "a mesh needs end > begin")]
+    fn graded_rejects_an_empty_layer()
+    {
+        Mesh::graded(1.0, 1.0, &GradedMesh::create(0.1, 0.2, 1.5));
+    }
+
+    #[test]
+    #[should_panic(expected = "a mesh needs end > begin")]
+    fn uniform_rejects_an_empty_layer()
+    {
+        Mesh::uniform(1.0, 0.5, 10);
+    }
+
+    #[test]
+    fn from_spec_selects_the_mesh()
+    {
+        let spec = GradedMesh::create(0.1, 0.3, 1.0);
+
+        assert_eq!(Mesh::from_spec(0.0, 1.0, &MeshSpec::Uniform(7)).points, Mesh::uniform(0.0, 1.0, 7).points);
+        assert_eq!(Mesh::from_spec(0.0, 1.0, &MeshSpec::Graded(spec.clone())).points, Mesh::graded(0.0, 1.0, &spec).points);
+    }
+
+    #[test]
+    fn adapt_keeps_the_ends_and_fixed_nodes()
+    {
+        let mesh = Mesh::uniform(0.0, 1.0, 10);
+        let fixed_nodes = [3, 4];
+
+        let coarse = mesh.adapt(&mesh.zeroVec(), 0.5, 1e-3, 1.0, &fixed_nodes);
+        assert!(coarse.len() < mesh.len());
+        assert_eq!(coarse.points[0], 0.0);
+        assert_eq!(coarse.points[coarse.lastIdx()], 1.0);
+        assert!(fixed_nodes.iter().all(|&i| coarse.points.contains(&mesh.points[i])));
+
+        let fine = mesh.adapt(&mesh.makeVec(2.0), 0.5, 1e-3, 1.0, &fixed_nodes);
+        assert_eq!(fine.len(), 2 * mesh.len() - 1);
+        assert_eq!(fine.points[0], 0.0);
+        assert_eq!(fine.points[fine.lastIdx()], 1.0);
+        assert!(fixed_nodes.iter().all(|&i| fine.points.contains(&mesh.points[i])));
+    }
+
+    #[test]
+    fn adapt_respects_the_step_bounds()
+    {
+        let mesh = Mesh::uniform(0.0, 1.0, 10);
+
+        assert_eq!(mesh.adapt(&mesh.makeVec(2.0), 0.5, 0.06, 1.0, &[]).points, mesh.points);
+        assert_eq!(mesh.adapt(&mesh.zeroVec(), 0.5, 1e-3, 0.15, &[]).points, mesh.points);
+    }
+
+    #[test]
+    fn find_point_within_the_tolerance()
+    {
+        let mesh = Mesh::create(vec![0.0, 1e-9, 3e-9, 7e-9]);
+
+        assert_eq!(mesh.find_point(3e-9), Some(2));
+        assert_eq!(mesh.find_point(3e-9 + 1e-16), Some(2));
+        assert_eq!(mesh.find_point(3e-9 - 1e-16), Some(2));
+        assert_eq!(mesh.find_point(0.0), Some(0));
+        assert_eq!(mesh.find_point(7e-9 + 1e-17), Some(3));
+        assert_eq!(mesh.find_point(2e-9), None);
+        assert_eq!(mesh.find_point(3e-9 + 1e-13), None);
+        assert_eq!(mesh.find_point(8e-9), None);
+    }
+
+    #[test]
+    fn interpolate_linear_data()
+    {
+        let mesh = Mesh::create(vec![0.0, 0.1, 0.4, 0.5, 1.0]);
+        let values = mesh.makeVecFn(|x, _| 2.0 - 3.0 * x);
+        let target = Mesh::create(vec![-0.5, 0.0, 0.05, 0.3, 0.45, 0.7, 1.0, 1.5]);
+
+        let result = mesh.interpolate(&values, &target);
+
+        for (i, &x) in target.points.iter().enumerate()
+        {
+            assert!((result[i] - (2.0 - 3.0 * x.clamp(0.0, 1.0))).abs() < 1e-12);
+        }
+    }
+}
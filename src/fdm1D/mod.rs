@@ -2,9 +2,12 @@
 // Everything is done using finite element 
 
 pub mod tridiag;
+pub mod blocktridiag;
 pub mod poission;
+pub mod continuity;
 pub mod mesh;
 
 pub use mesh::*;
 pub use poission::*;
+pub use continuity::*;
 
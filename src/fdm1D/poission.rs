@@ -11,6 +11,16 @@ pub struct PoissionProblem
 {
     pub operator: MatTriDiag,
     pub scratch: VecD,
+    // width of the control volume around each node, a sheet charge at a node acts as sheet / control_width
+    pub control_width: VecD,
+    // interface sheet charges (C/m^2) spread over the control width of their node
+    pub sheet_charge_density: VecD,
+}
+
+// width of the control volume around node i
+fn node_control_width(h:&VecD, i:usize) -> f64
+{
+    0.5 * (h[i - 1] + h[i])
 }
 
 impl PoissionProblem
@@ -21,12 +31,14 @@ impl PoissionProblem
         let mut subdiag = mesh.zeroVec();
         let mut diag = mesh.zeroVec();
         let mut superdiag = mesh.zeroVec();
+        let mut control_width = mesh.zeroVec();
         // set the operator
         let h = mesh.calcStepVec();
 
         for i in 1..mesh.lastIdx()
         {
-            let h_avg = 0.5 * (h[i] + h[i + 1]);
+            let h_avg = node_control_width(&h, i);
+            control_width[i] = h_avg;
             let coeff_f = (epsilon[i + 1] + epsilon[i]) / (2.0 * h[i]);
             let coeff_b = (epsilon[i - 1] + epsilon[i]) / (2.0 * h[i - 1]);
 
@@ -41,13 +53,27 @@ impl PoissionProblem
 
         PoissionProblem{
             operator: (subdiag, diag, superdiag),
-            scratch:mesh.zeroVec()
+            scratch:mesh.zeroVec(),
+            control_width,
+            sheet_charge_density:mesh.zeroVec()
+        }
+    }
+
+    // sheet charge per node (C/m^2), the contacts hold the potential so their sheet charge is dropped
+    pub fn set_sheet_charge(&mut self, sheet_charge:&VecD)
+    {
+        let last_idx = sheet_charge.len() - 1;
+
+        self.sheet_charge_density = VecD::zeros(sheet_charge.len());
+        for i in 1..last_idx
+        {
+            self.sheet_charge_density[i] = sheet_charge[i] / self.control_width[i];
         }
     }
 
     pub fn solve(&mut self, charge:&VecD, left_bc:f64, right_bc:f64) -> VecD
     {
-        let mut load_vector = -charge.clone();
+        let mut load_vector = -(charge + &self.sheet_charge_density);
         
         // apply boundary conditions
         load_vector[0] = left_bc;
@@ -58,7 +84,7 @@ impl PoissionProblem
 
     pub fn residue(&self, potential:&VecD, charge:&VecD) -> VecD
     {
-        return tridiag::apply(&self.operator, &potential) + charge;
+        return tridiag::apply(&self.operator, &potential) + charge + &self.sheet_charge_density;
     }
 }
 
@@ -14,8 +14,9 @@ use rgsl::error;
 use rgsl::Value;
 use semiconductor as sc;
 use common::*;
+use fdm1D::{GradedMesh, MeshSpec};
 use pyvi::PyVi;
-use devices::device::Device;
+use devices::device::{Device, RefinementCriteria};
 
 fn error_handling(error_str: &str, file: &str, line: u32, error_value: Value) {
     println!("RGSL [{:?}] '{}:{}': {}", error_value, file, line, error_str);
@@ -56,11 +57,14 @@ fn main() {
     let mut top_layer = sc::Semiconductor::create(AlGaAs);
     top_layer.push_dopant(silicon);
 
-    let sample_count = 64*4096u32;
+    // steps graded down toward the heterointerface
+    let mut graded_mesh = GradedMesh::create(1e-10, len / 64.0, 1.2);
+    graded_mesh.grade_to_interfaces(1e-9);
+    let mesh_spec = MeshSpec::Graded(graded_mesh);
     
     let mut device  = Device::create(temp);
-    device.push_bulk_layer(bottom_layer, len, sample_count);
-    device.push_bulk_layer(top_layer, len, sample_count);
+    device.push_bulk_layer(bottom_layer, len, &mesh_spec);
+    device.push_bulk_layer(top_layer, len, &mesh_spec);
 // */
 /* 
     let silicon = sc::Bulk::create_silicon_300K();
@@ -83,13 +87,15 @@ fn main() {
     let mut bottom_layer = sc::Semiconductor::create(silicon);
     bottom_layer.push_dopant(boron);
     bottom_layer.push_dopant(phosphorus);
-    let sample_count = 4096u32;
+    let mesh_spec = MeshSpec::Uniform(4096);
     
     let mut device  = Device::create(temp);
-    device.push_bulk_layer(bottom_layer, len, sample_count);
+    device.push_bulk_layer(bottom_layer, len, &mesh_spec);
     // */
 
-    device.calc_steady_state(1e1, 1e-8, 500);
+    // refine the mesh around the junction and the heterointerface from the coarse samples
+    let criteria = RefinementCriteria::create(1e-3, 0.5, 1e-11, len / 16.0);
+    device.calc_steady_state_adaptive(&criteria, 12, 1e1, 1e-8, 500);
     println!("built-in potential: {:.4} V", device.steady_state.built_in_potential);
     println!("Steady State fermi-level(relative to Vaccum): {:.4} eV", device.steady_state.fermi_lvl / constants::Q);
 
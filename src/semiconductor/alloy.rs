@@ -0,0 +1,322 @@
+use super::bulk::*;
+use super::mobility::*;
+
+// bowing parameters of a ternary A_x B_1-x C made from the binaries AC and BC
+// P(x) = x P_AC + (1 - x) P_BC - x (1 - x) bowing, in the units of the Bulk fields (J, kg, m^2/Vs)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bowing
+{
+    pub electron_affinity:f64,
+    pub band_gap:f64,
+    pub relative_permitivity:f64,
+    pub electron_mass:f64,
+    pub hole_mass:f64,
+    pub electron_mobility:f64,
+    pub hole_mobility:f64,
+}
+
+const PARAMETER_COUNT:usize = 7;
+
+impl Bowing
+{
+    pub fn create_band_gap(band_gap:f64) -> Bowing
+    {
+        Bowing {
+            band_gap,
+            ..Default::default()
+        }
+    }
+
+    fn as_array(&self) -> [f64; PARAMETER_COUNT]
+    {
+        [
+            self.electron_affinity,
+            self.band_gap,
+            self.relative_permitivity,
+            self.electron_mass,
+            self.hole_mass,
+            self.electron_mobility,
+            self.hole_mobility,
+        ]
+    }
+}
+
+fn bulk_parameters(bulk:&Bulk) -> [f64; PARAMETER_COUNT]
+{
+    [
+        bulk.electron_affinity,
+        bulk.band_gap,
+        bulk.relative_permitivity,
+        bulk.electron_properties.effectiveMass,
+        bulk.hole_properties.effectiveMass,
+        bulk.electron_properties.mobility,
+        bulk.hole_properties.mobility,
+    ]
+}
+
+fn ternary_parameters(ac:&[f64; PARAMETER_COUNT], bc:&[f64; PARAMETER_COUNT], x:f64, bowing:&Bowing) -> [f64; PARAMETER_COUNT]
+{
+    let bowing = bowing.as_array();
+    let mut parameters = [0.0; PARAMETER_COUNT];
+
+    for k in 0..PARAMETER_COUNT
+    {
+        parameters[k] = x * ac[k] + (1.0 - x) * bc[k] - x * (1.0 - x) * bowing[k];
+    }
+
+    parameters
+}
+
+// weighted mean of optional parameters, None if any endpoint lacks it
+fn weighted_option<T:Copy>(endpoints:&[(f64, Option<T>)], combine:impl Fn(&[(f64, T)]) -> T) -> Option<T>
+{
+    let values = endpoints.iter()
+        .map(|(w, v)| v.map(|v| (*w, v)))
+        .collect::<Option<Vec<(f64, T)>>>()?;
+
+    Some(combine(&values))
+}
+
+fn weighted_sum<T>(values:&[(f64, T)], f:impl Fn(&T) -> f64) -> f64
+{
+    values.iter().map(|(w, v)| w * f(v)).sum()
+}
+
+fn interpolate_mobility(endpoints:&[(f64, MobilityModel)]) -> MobilityModel
+{
+    let doping = weighted_option(&endpoints.iter().map(|(w, m)| (*w, m.doping)).collect::<Vec<_>>(), |values| DopingMobility {
+        min_mobility:weighted_sum(values, |d| d.min_mobility),
+        ref_conc:weighted_sum(values, |d| d.ref_conc),
+        alpha:weighted_sum(values, |d| d.alpha),
+    });
+
+    let field = weighted_option(&endpoints.iter().map(|(w, m)| (*w, m.field)).collect::<Vec<_>>(), |values| FieldMobility {
+        saturation_velocity:weighted_sum(values, |f| f.saturation_velocity),
+        beta:weighted_sum(values, |f| f.beta),
+    });
+
+    MobilityModel::create(weighted_sum(endpoints, |m| m.temp_exponent), doping, field)
+}
+
+// build the alloy bulk from the interpolated parameters, the endpoints are weighted by their linear interpolation weights
+// mobility, band temperature, deformation and polarization models and the valence band offset are interpolated linearly, conduction valleys too if all endpoints
+// have the same valleys, recombination and generation models are taken from the dominant endpoint
+fn create_alloy(parameters:[f64; PARAMETER_COUNT], endpoints:&[(f64, &Bulk)]) -> Bulk
+{
+    let temp = endpoints[0].1.temp;
+    if endpoints.iter().any(|(_, bulk)| bulk.temp != temp)
+    {
+        panic!("Error: alloy endpoints must be at the same temperature");
+    }
+
+    let hole_properties = CarrrierInfo{
+        mobility:parameters[6],
+        effectiveMass:parameters[4],
+    };
+
+    let electron_properties = CarrrierInfo{
+        mobility:parameters[5],
+        effectiveMass:parameters[3],
+    };
+
+    let mut bulk = Bulk::create(parameters[0], parameters[1], parameters[2], hole_properties, electron_properties);
+    bulk.temp = temp;
+    bulk.non_parabolicity = weighted_sum(endpoints, |b| b.non_parabolicity);
+
+    bulk.electron_mobility = interpolate_mobility(&endpoints.iter().map(|(w, b)| (*w, b.electron_mobility)).collect::<Vec<_>>());
+    bulk.hole_mobility = interpolate_mobility(&endpoints.iter().map(|(w, b)| (*w, b.hole_mobility)).collect::<Vec<_>>());
+
+    bulk.band_temperature = weighted_option(&endpoints.iter().map(|(w, b)| (*w, b.band_temperature)).collect::<Vec<_>>(), |values| BandTemperature {
+        varshni_alpha:weighted_sum(values, |t| t.varshni_alpha),
+        varshni_beta:weighted_sum(values, |t| t.varshni_beta),
+        affinity_coeff:weighted_sum(values, |t| t.affinity_coeff),
+    });
+
+    // Vegard's law for the lattice and deformation parameters
+    bulk.deformation = weighted_option(&endpoints.iter().map(|(w, b)| (*w, b.deformation)).collect::<Vec<_>>(), |values| Deformation {
+        structure:if values.iter().all(|(_, d)| d.structure == values[0].1.structure) {
+            values[0].1.structure
+        } else {
+            panic!("Error: alloy components have different crystal structures")
+        },
+        lattice_constant:weighted_sum(values, |d| d.lattice_constant),
+        c11:weighted_sum(values, |d| d.c11),
+        c12:weighted_sum(values, |d| d.c12),
+        c13:weighted_sum(values, |d| d.c13),
+        c33:weighted_sum(values, |d| d.c33),
+        conduction:weighted_sum(values, |d| d.conduction),
+        valence:weighted_sum(values, |d| d.valence),
+        shear:weighted_sum(values, |d| d.shear),
+    });
+
+    bulk.polarization = weighted_option(&endpoints.iter().map(|(w, b)| (*w, b.polarization)).collect::<Vec<_>>(), |values| Polarization {
+        spontaneous:weighted_sum(values, |p| p.spontaneous),
+        e31:weighted_sum(values, |p| p.e31),
+        e33:weighted_sum(values, |p| p.e33),
+        c13:weighted_sum(values, |p| p.c13),
+        c33:weighted_sum(values, |p| p.c33),
+    });
+
+    bulk.valence_band_offset = weighted_option(&endpoints.iter().map(|(w, b)| (*w, b.valence_band_offset)).collect::<Vec<_>>(), |values| weighted_sum(values, |v| *v));
+
+    let valley_count = endpoints[0].1.conduction_valleys.len();
+    if endpoints.iter().all(|(_, b)| b.conduction_valleys.len() == valley_count)
+    {
+        bulk.conduction_valleys = (0..valley_count).map(|k| {
+            let valleys = endpoints.iter().map(|(w, b)| (*w, b.conduction_valleys[k])).collect::<Vec<_>>();
+
+            Valley::create_non_parabolic(
+                weighted_sum(&valleys, |v| v.energy_offset),
+                weighted_sum(&valleys, |v| v.effectiveMass),
+                weighted_sum(&valleys, |v| v.degeneracy),
+                weighted_sum(&valleys, |v| v.non_parabolicity)
+            )
+        }).collect();
+    }
+
+    let (_, dominant) = endpoints.iter()
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .expect("Error: alloy without endpoints");
+
+    bulk.recombination = dominant.recombination.clone();
+    bulk.impact_ionization = dominant.impact_ionization;
+    bulk.band_to_band = dominant.band_to_band;
+    bulk.band_gap_narrowing = dominant.band_gap_narrowing;
+
+    bulk
+}
+
+impl Bulk
+{
+    // ternary A_x B_1-x C from the binaries AC and BC
+    pub fn create_ternary(ac:&Bulk, bc:&Bulk, x:f64, bowing:&Bowing) -> Bulk
+    {
+        if !(0.0..=1.0).contains(&x) { panic!("Error: The mole_fraction must be a value between 0 and 1."); }
+
+        let parameters = ternary_parameters(&bulk_parameters(ac), &bulk_parameters(bc), x, bowing);
+
+        create_alloy(parameters, &[(x, ac), (1.0 - x, bc)])
+    }
+
+    // quaternary A_x B_1-x C_y D_1-y from the binaries AC, AD, BC and BD
+    // bowing holds the ternaries ABC (in x), ABD (in x), ACD (in y) and BCD (in y), the ternaries are
+    // weighted as Q = [x(1-x) (y T_ABC + (1-y) T_ABD) + y(1-y) (x T_ACD + (1-x) T_BCD)] / [x(1-x) + y(1-y)]
+    pub fn create_quaternary(ac:&Bulk, ad:&Bulk, bc:&Bulk, bd:&Bulk, x:f64, y:f64, bowing:[&Bowing; 4]) -> Bulk
+    {
+        if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) { panic!("Error: The mole_fraction must be a value between 0 and 1."); }
+
+        let (p_ac, p_ad, p_bc, p_bd) = (bulk_parameters(ac), bulk_parameters(ad), bulk_parameters(bc), bulk_parameters(bd));
+        let endpoints = [(x * y, ac), (x * (1.0 - y), ad), ((1.0 - x) * y, bc), ((1.0 - x) * (1.0 - y), bd)];
+
+        let weight_x = x * (1.0 - x);
+        let weight_y = y * (1.0 - y);
+
+        // at the corners the alloy is one of the binaries
+        if weight_x + weight_y == 0.0
+        {
+            let (_, corner) = endpoints.iter().max_by(|a, b| a.0.total_cmp(&b.0)).unwrap();
+            return create_alloy(bulk_parameters(corner), &endpoints);
+        }
+
+        let t_abc = ternary_parameters(&p_ac, &p_bc, x, bowing[0]);
+        let t_abd = ternary_parameters(&p_ad, &p_bd, x, bowing[1]);
+        let t_acd = ternary_parameters(&p_ac, &p_ad, y, bowing[2]);
+        let t_bcd = ternary_parameters(&p_bc, &p_bd, y, bowing[3]);
+
+        let mut parameters = [0.0; PARAMETER_COUNT];
+        for k in 0..PARAMETER_COUNT
+        {
+            parameters[k] = (weight_x * (y * t_abc[k] + (1.0 - y) * t_abd[k]) + weight_y * (x * t_acd[k] + (1.0 - x) * t_bcd[k])) / (weight_x + weight_y);
+        }
+
+        create_alloy(parameters, &endpoints)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::common::*;
+
+    // distinct binaries, k selects the parameter set
+    fn binary(k:f64) -> Bulk
+    {
+        Bulk::create(
+            constants::from_eV(4.0 + 0.1 * k),
+            constants::from_eV(0.5 + 0.4 * k),
+            12.0 + k,
+            CarrrierInfo { effectiveMass:(0.4 + 0.05 * k) * constants::ELECTRON_MASS, mobility:0.04 - 0.005 * k },
+            CarrrierInfo { effectiveMass:(0.02 + 0.03 * k) * constants::ELECTRON_MASS, mobility:1.0 - 0.2 * k }
+        )
+    }
+
+    fn bowing(k:f64) -> Bowing
+    {
+        Bowing {
+            electron_affinity:constants::from_eV(0.1 * k),
+            band_gap:constants::from_eV(0.5 + 0.1 * k),
+            relative_permitivity:0.3,
+            electron_mass:0.01 * constants::ELECTRON_MASS,
+            hole_mass:0.02 * constants::ELECTRON_MASS,
+            electron_mobility:0.1,
+            hole_mobility:0.01,
+        }
+    }
+
+    fn assert_same_parameters(a:&Bulk, b:&Bulk)
+    {
+        for (pa, pb) in bulk_parameters(a).iter().zip(bulk_parameters(b).iter())
+        {
+            assert!((pa - pb).abs() <= 1e-12 * pb.abs(), "{} != {}", pa, pb);
+        }
+        assert!((a.Ec - b.Ec).abs() <= 1e-12 * b.Ec.abs());
+        assert!((a.Ev - b.Ev).abs() <= 1e-12 * b.Ev.abs());
+    }
+
+    #[test]
+    fn ternary_ends_are_the_binaries()
+    {
+        let (ac, bc) = (binary(0.0), binary(1.0));
+
+        assert_same_parameters(&Bulk::create_ternary(&ac, &bc, 1.0, &bowing(0.0)), &ac);
+        assert_same_parameters(&Bulk::create_ternary(&ac, &bc, 0.0, &bowing(0.0)), &bc);
+    }
+
+    #[test]
+    fn positive_bowing_lowers_the_ternary()
+    {
+        let (ac, bc) = (binary(0.0), binary(1.0));
+        let linear = Bulk::create_ternary(&ac, &bc, 0.5, &Bowing::default());
+        let bowed = Bulk::create_ternary(&ac, &bc, 0.5, &Bowing::create_band_gap(constants::from_eV(0.4)));
+
+        assert!((linear.band_gap - 0.5 * (ac.band_gap + bc.band_gap)).abs() < 1e-12 * linear.band_gap);
+        assert!((linear.band_gap - bowed.band_gap - 0.25 * constants::from_eV(0.4)).abs() < 1e-12 * linear.band_gap);
+    }
+
+    #[test]
+    fn quaternary_corners_are_the_binaries()
+    {
+        let (ac, ad, bc, bd) = (binary(0.0), binary(1.0), binary(2.0), binary(3.0));
+        let bowings = [bowing(0.0), bowing(1.0), bowing(2.0), bowing(3.0)];
+        let quaternary = |x, y| Bulk::create_quaternary(&ac, &ad, &bc, &bd, x, y, [&bowings[0], &bowings[1], &bowings[2], &bowings[3]]);
+
+        assert_same_parameters(&quaternary(1.0, 1.0), &ac);
+        assert_same_parameters(&quaternary(1.0, 0.0), &ad);
+        assert_same_parameters(&quaternary(0.0, 1.0), &bc);
+        assert_same_parameters(&quaternary(0.0, 0.0), &bd);
+    }
+
+    #[test]
+    fn quaternary_edges_are_the_ternaries()
+    {
+        let (ac, ad, bc, bd) = (binary(0.0), binary(1.0), binary(2.0), binary(3.0));
+        let bowings = [bowing(0.0), bowing(1.0), bowing(2.0), bowing(3.0)];
+        let quaternary = |x, y| Bulk::create_quaternary(&ac, &ad, &bc, &bd, x, y, [&bowings[0], &bowings[1], &bowings[2], &bowings[3]]);
+
+        assert_same_parameters(&quaternary(0.3, 1.0), &Bulk::create_ternary(&ac, &bc, 0.3, &bowings[0]));
+        assert_same_parameters(&quaternary(0.3, 0.0), &Bulk::create_ternary(&ad, &bd, 0.3, &bowings[1]));
+        assert_same_parameters(&quaternary(1.0, 0.6), &Bulk::create_ternary(&ac, &ad, 0.6, &bowings[2]));
+        assert_same_parameters(&quaternary(0.0, 0.6), &Bulk::create_ternary(&bc, &bd, 0.6, &bowings[3]));
+    }
+}
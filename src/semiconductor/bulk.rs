@@ -2,6 +2,11 @@ use core::f64;
 
 use crate::common::*;
 use rgsl::fermi_dirac::complete_integrals::*;
+use super::recombination::{self, Recombination};
+use super::impact::ImpactIonization;
+use super::tunneling::BandToBand;
+use super::mobility::MobilityModel;
+use super::narrowing::BandGapNarrowing;
 
 #[derive(Debug)]
 pub struct CarrrierInfo
@@ -10,6 +15,68 @@ pub struct CarrrierInfo
     pub mobility:f64,
 }
 
+// conduction band valley, energy_offset is measured from Ec, effectiveMass is the band edge density of states mass
+// of a single valley and degeneracy the number of equivalent valleys
+// non_parabolicity is the Kane parameter alpha (1/J) of E (1 + alpha E) = hbar^2 k^2 / 2m
+#[derive(Debug, Clone, Copy)]
+pub struct Valley
+{
+    pub energy_offset:f64,
+    pub effectiveMass:f64,
+    pub degeneracy:f64,
+    pub non_parabolicity:f64,
+}
+
+// temperature dependence of the band edges, the band gap follows Varshni, Eg(T) = Eg(0) - alpha T^2 / (T + beta),
+// and the affinity changes linearly, d(affinity)/dT = affinity_coeff
+#[derive(Debug, Clone, Copy)]
+pub struct BandTemperature
+{
+    pub varshni_alpha:f64,
+    pub varshni_beta:f64,
+    pub affinity_coeff:f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrystalStructure
+{
+    // cubic, strained on a (001) substrate
+    Zincblende,
+    // hexagonal, strained on a (0001) substrate
+    Wurtzite,
+}
+
+// lattice constant (m), elastic constants (Pa) and deformation potentials (J) for pseudomorphic strain, the
+// perpendicular strain is -2 c12 / c11 e_parallel for zincblende and -2 c13 / c33 e_parallel for wurtzite
+// the hydrostatic potentials move the band edges by conduction tr(e) and valence tr(e),
+// the shear potential b splits the heavy and light holes by -+ b (e_parallel - e_perpendicular)
+#[derive(Debug, Clone, Copy)]
+pub struct Deformation
+{
+    pub structure:CrystalStructure,
+    pub lattice_constant:f64,
+    pub c11:f64,
+    pub c12:f64,
+    pub c13:f64,
+    pub c33:f64,
+    pub conduction:f64,
+    pub valence:f64,
+    pub shear:f64,
+}
+
+// polarization (C/m^2) of wurtzite materials along the growth direction, which is taken as increasing x
+// (metal face growth has negative spontaneous polarization), the piezoelectric part of a layer strained to
+// a substrate is 2 e_parallel (e31 - e33 c13 / c33) with the elastic constants c13 and c33 in Pa
+#[derive(Debug, Clone, Copy)]
+pub struct Polarization
+{
+    pub spontaneous:f64,
+    pub e31:f64,
+    pub e33:f64,
+    pub c13:f64,
+    pub c33:f64,
+}
+
 #[derive(Debug)]
 pub struct Bulk
 {
@@ -18,6 +85,23 @@ pub struct Bulk
     pub relative_permitivity:f64,
     pub hole_properties:CarrrierInfo,
     pub electron_properties:CarrrierInfo,
+    // conduction band valleys, electron_properties and non_parabolicity are used as a single valley at Ec if empty
+    pub conduction_valleys:Vec<Valley>,
+    // Kane non-parabolicity (1/J) of the single conduction valley
+    pub non_parabolicity:f64,
+    pub electron_mobility:MobilityModel,
+    pub hole_mobility:MobilityModel,
+    pub recombination:Vec<Recombination>,
+    pub impact_ionization:Option<ImpactIonization>,
+    pub band_to_band:Option<BandToBand>,
+    pub band_gap_narrowing:Option<BandGapNarrowing>,
+    pub band_temperature:Option<BandTemperature>,
+    pub deformation:Option<Deformation>,
+    pub polarization:Option<Polarization>,
+    // valence band edge on the common energy scale of the band offset tables, used by the valence band offset alignment
+    pub valence_band_offset:Option<f64>,
+    // temperature the band parameters refer to
+    pub temp:f64,
     
     pub Ev:f64,
     pub Ec:f64,
@@ -32,6 +116,123 @@ impl CarrrierInfo
     }
 }
 
+impl Valley
+{
+    pub fn create(energy_offset:f64, effectiveMass:f64, degeneracy:f64) -> Valley
+    {
+        Valley {
+            energy_offset,
+            effectiveMass,
+            degeneracy,
+            non_parabolicity:0.0
+        }
+    }
+
+    pub fn create_non_parabolic(energy_offset:f64, effectiveMass:f64, degeneracy:f64, non_parabolicity:f64) -> Valley
+    {
+        Valley {
+            energy_offset,
+            effectiveMass,
+            degeneracy,
+            non_parabolicity
+        }
+    }
+
+    fn density_of_states(&self, temp:f64) -> f64
+    {
+        self.degeneracy * 2.0 * (2.0 * f64::consts::PI * self.effectiveMass * constants::K * temp).powf(1.5) / constants::PLANK_CONST.powi(3)
+    }
+}
+
+impl BandTemperature
+{
+    pub fn create(varshni_alpha:f64, varshni_beta:f64, affinity_coeff:f64) -> BandTemperature
+    {
+        BandTemperature {
+            varshni_alpha,
+            varshni_beta,
+            affinity_coeff
+        }
+    }
+
+    // band gap shrinkage alpha T^2 / (T + beta) relative to 0 K
+    pub fn gap_shrinkage(&self, temp:f64) -> f64
+    {
+        self.varshni_alpha * temp * temp / (temp + self.varshni_beta)
+    }
+}
+
+impl Deformation
+{
+    // zincblende, c13 and c33 equal c12 and c11 by the cubic symmetry
+    pub fn create(lattice_constant:f64, c11:f64, c12:f64, conduction:f64, valence:f64, shear:f64) -> Deformation
+    {
+        Deformation {
+            structure:CrystalStructure::Zincblende,
+            lattice_constant,
+            c11,
+            c12,
+            c13:c12,
+            c33:c11,
+            conduction,
+            valence,
+            shear
+        }
+    }
+
+    // wurtzite with the elastic constants c13 and c33, lattice_constant is the in plane constant a
+    pub fn set_wurtzite(&mut self, c13:f64, c33:f64)
+    {
+        self.structure = CrystalStructure::Wurtzite;
+        self.c13 = c13;
+        self.c33 = c33;
+    }
+
+    // (parallel, perpendicular) strain of a layer grown pseudomorphically on a substrate
+    pub fn strain(&self, substrate_lattice_constant:f64) -> (f64, f64)
+    {
+        let parallel = (substrate_lattice_constant - self.lattice_constant) / self.lattice_constant;
+
+        let ratio = match self.structure {
+            CrystalStructure::Zincblende => self.c12 / self.c11,
+            CrystalStructure::Wurtzite => self.c13 / self.c33,
+        };
+
+        (parallel, -2.0 * ratio * parallel)
+    }
+
+    // band edge shifts (conduction, heavy hole, light hole) on a substrate, the spin orbit coupling of the
+    // light holes is neglected
+    pub fn band_edge_shift(&self, substrate_lattice_constant:f64) -> (f64, f64, f64)
+    {
+        let (parallel, perpendicular) = self.strain(substrate_lattice_constant);
+        let hydrostatic = 2.0 * parallel + perpendicular;
+        let shear = self.shear * (parallel - perpendicular);
+
+        (self.conduction * hydrostatic, self.valence * hydrostatic + shear, self.valence * hydrostatic - shear)
+    }
+}
+
+impl Polarization
+{
+    pub fn create(spontaneous:f64, e31:f64, e33:f64, c13:f64, c33:f64) -> Polarization
+    {
+        Polarization {
+            spontaneous,
+            e31,
+            e33,
+            c13,
+            c33
+        }
+    }
+
+    // spontaneous and piezoelectric polarization for the in plane strain e_parallel
+    pub fn total(&self, parallel_strain:f64) -> f64
+    {
+        self.spontaneous + 2.0 * parallel_strain * (self.e31 - self.e33 * self.c13 / self.c33)
+    }
+}
+
 impl Bulk {
     pub fn create(electron_affinity:f64, band_gap:f64, relative_permitivity:f64, hole_properties:CarrrierInfo, electron_properties:CarrrierInfo) -> Bulk
     {
@@ -41,6 +242,19 @@ impl Bulk {
             relative_permitivity,
             hole_properties,
             electron_properties,
+            conduction_valleys:Vec::new(),
+            non_parabolicity:0.0,
+            electron_mobility:MobilityModel::default(),
+            hole_mobility:MobilityModel::default(),
+            recombination:Vec::new(),
+            impact_ionization:None,
+            band_to_band:None,
+            band_gap_narrowing:None,
+            band_temperature:None,
+            deformation:None,
+            polarization:None,
+            valence_band_offset:None,
+            temp:300.0,
             Ev: -electron_affinity-band_gap,
             Ec: -electron_affinity,
             epsilon: constants::EPSILON_VACCUM * relative_permitivity,
@@ -59,12 +273,26 @@ impl Bulk {
             effectiveMass:1.08*constants::ELECTRON_MASS,
         };
 
+        // ref: https://www.ioffe.ru/SVA/NSM/Semicond/Si/bandstr.html
         Bulk {
             electron_affinity:1.3895213 * constants::Q,
             band_gap:1.14 * constants::Q,
             relative_permitivity:11.68,
             hole_properties,
             electron_properties,
+            conduction_valleys:Vec::new(),
+            non_parabolicity:0.0,
+            electron_mobility:MobilityModel::default(),
+            hole_mobility:MobilityModel::default(),
+            recombination:Vec::new(),
+            impact_ionization:None,
+            band_to_band:None,
+            band_gap_narrowing:None,
+            band_temperature:Some(BandTemperature::create(constants::from_eV(4.73e-4), 636.0, constants::from_eV(1.27e-4))),
+            deformation:None,
+            polarization:None,
+            valence_band_offset:None,
+            temp:300.0,
             Ev: (-1.3895213-1.14) * constants::Q,
             Ec: -1.3895213 * constants::Q,
             epsilon: constants::EPSILON_VACCUM * 11.68,
@@ -85,13 +313,20 @@ impl Bulk {
             effectiveMass:0.063*constants::ELECTRON_MASS,
         };
 
-        Bulk::create(
+        let mut bulk = Bulk::create(
             constants::from_eV(4.07), 
             constants::from_eV(1.42),
             12.9,
             hole_properties,
             electron_properties
-        )
+        );
+
+        bulk.band_temperature = Some(BandTemperature::create(constants::from_eV(5.405e-4), 204.0, constants::from_eV(2.26e-4)));
+        // ref: I. Vurgaftman et al., J. Appl. Phys. 89, 5815 (2001)
+        bulk.deformation = Some(Deformation::create(5.65325e-10, 122.1e9, 56.6e9, constants::from_eV(-7.17), constants::from_eV(1.16), constants::from_eV(-2.0)));
+        bulk.valence_band_offset = Some(constants::from_eV(-0.80));
+
+        bulk
     }
 
     pub fn create_AlGaAs_300K(x:f64) -> Bulk
@@ -115,13 +350,20 @@ impl Bulk {
                 effectiveMass:(0.063 + 0.083*x)*constants::ELECTRON_MASS,
             };
         
-            Bulk::create(
+            let mut bulk = Bulk::create(
                 constants::from_eV(4.07 - 1.1*x), 
                 constants::from_eV(Eg),
                 12.9 - 2.84 * x,
                 hole_prop,
                 elec_prop
-            )
+            );
+
+            // direct gap, GaAs like
+            bulk.band_temperature = Some(BandTemperature::create(constants::from_eV(5.405e-4), 204.0, constants::from_eV(2.26e-4)));
+            bulk.set_AlGaAs_valleys(x);
+            bulk.set_AlGaAs_deformation(x);
+
+            bulk
         }
         else
         {
@@ -137,31 +379,190 @@ impl Bulk {
                 effectiveMass:(0.85 - 0.14*x)*constants::ELECTRON_MASS,
             };
 
-            Bulk::create(
+            let mut bulk = Bulk::create(
                 constants::from_eV(3.64 - 0.14*x), 
                 constants::from_eV(Eg),
                 12.9 - 2.84 * x,
                 hole_prop,
                 elec_prop
-            )
+            );
+
+            // indirect X gap, AlAs like
+            bulk.band_temperature = Some(BandTemperature::create(constants::from_eV(7.0e-4), 530.0, constants::from_eV(2.07e-4)));
+            bulk.set_AlGaAs_valleys(x);
+            bulk.set_AlGaAs_deformation(x);
+
+            bulk
         }
     }
 
+    // Gamma, X and L valleys of Al_x Ga_1-x As measured from the lowest one, the X and L masses are
+    // the density of states masses of all equivalent valleys
+    fn set_AlGaAs_valleys(&mut self, x:f64)
+    {
+        let Eg_gamma = if x < 0.45 { 1.424 + 1.247*x } else { 1.424 + 1.247*x + 1.147*(x - 0.45)*(x - 0.45) };
+        let Eg_X = 1.9 + 0.125*x + 0.143*x*x;
+        let Eg_L = 1.708 + 0.642*x;
+        let Eg = Eg_gamma.min(Eg_X).min(Eg_L);
+
+        self.conduction_valleys = vec![
+            Valley::create(constants::from_eV(Eg_gamma - Eg), (0.063 + 0.083*x)*constants::ELECTRON_MASS, 1.0),
+            Valley::create(constants::from_eV(Eg_X - Eg), (0.85 - 0.14*x)*constants::ELECTRON_MASS, 1.0),
+            Valley::create(constants::from_eV(Eg_L - Eg), (0.56 + 0.1*x)*constants::ELECTRON_MASS, 1.0),
+        ];
+    }
+
+    // Vegard interpolation between GaAs and AlAs, the valence band offset too
+    // ref: I. Vurgaftman et al., J. Appl. Phys. 89, 5815 (2001)
+    fn set_AlGaAs_deformation(&mut self, x:f64)
+    {
+        self.valence_band_offset = Some(constants::from_eV(-0.80 - 0.53*x));
+
+        self.deformation = Some(Deformation::create(
+            (5.65325 + 0.00785*x) * 1e-10,
+            (122.1 + 2.9*x) * 1e9,
+            (56.6 - 3.2*x) * 1e9,
+            constants::from_eV(-7.17 + 1.53*x),
+            constants::from_eV(1.16 + 1.31*x),
+            constants::from_eV(-2.0 - 0.3*x)
+        ));
+    }
+
+    pub fn push_conduction_valley(&mut self, valley:Valley)
+    {
+        self.conduction_valleys.push(valley);
+    }
+
+    pub fn create_silicon(temp:f64) -> Bulk
+    {
+        let mut bulk = Bulk::create_silicon_300K();
+        bulk.set_temperature(temp);
+        bulk
+    }
+
+    pub fn create_GaAs(temp:f64) -> Bulk
+    {
+        let mut bulk = Bulk::create_GaAs_300K();
+        bulk.set_temperature(temp);
+        bulk
+    }
+
+    pub fn create_AlGaAs(x:f64, temp:f64) -> Bulk
+    {
+        let mut bulk = Bulk::create_AlGaAs_300K(x);
+        bulk.set_temperature(temp);
+        bulk
+    }
+
+    // move the band gap and affinity from self.temp to temp, the density of states and mobilities
+    // take the temperature when they are evaluated
+    // dopant levels are absolute energies, Semiconductor::set_temperature moves the levels of a layer with its bulk
+    pub fn set_temperature(&mut self, temp:f64)
+    {
+        if let Some(model) = &self.band_temperature
+        {
+            self.band_gap += model.gap_shrinkage(self.temp) - model.gap_shrinkage(temp);
+            self.electron_affinity += model.affinity_coeff * (temp - self.temp);
+
+            self.Ec = -self.electron_affinity;
+            self.Ev = -self.electron_affinity - self.band_gap;
+        }
+
+        self.temp = temp;
+    }
+
+    // strain the band edges for pseudomorphic growth on a substrate, the valence band edge is the upper of the
+    // heavy and light hole bands and all conduction valleys move with Ec
+    // returns the shifts (conduction, heavy hole, light hole), zero without deformation parameters
+    pub fn apply_strain(&mut self, substrate_lattice_constant:f64) -> (f64, f64, f64)
+    {
+        let shift = match &self.deformation {
+            Some(deformation) => deformation.band_edge_shift(substrate_lattice_constant),
+            None => return (0.0, 0.0, 0.0),
+        };
+
+        self.Ec += shift.0;
+        self.Ev += shift.1.max(shift.2);
+
+        self.electron_affinity = -self.Ec;
+        self.band_gap = self.Ec - self.Ev;
+
+        shift
+    }
+
+    // rigid shift of both band edges, used to align the bands of a heterostructure
+    pub fn shift_bands(&mut self, shift:f64)
+    {
+        self.Ec += shift;
+        self.Ev += shift;
+
+        self.electron_affinity = -self.Ec;
+    }
+
+    // polarization along the growth direction, strained to the substrate if there is one, zero for
+    // materials without polarization and only the spontaneous part without deformation parameters
+    pub fn total_polarization(&self, substrate_lattice_constant:Option<f64>) -> f64
+    {
+        let polarization = match &self.polarization {
+            Some(polarization) => polarization,
+            None => return 0.0,
+        };
+
+        let parallel_strain = match (&self.deformation, substrate_lattice_constant) {
+            (Some(deformation), Some(lattice_constant)) => deformation.strain(lattice_constant).0,
+            _ => 0.0,
+        };
+
+        polarization.total(parallel_strain)
+    }
+
+    pub fn set_mobility_models(&mut self, electron_mobility:MobilityModel, hole_mobility:MobilityModel)
+    {
+        self.electron_mobility = electron_mobility;
+        self.hole_mobility = hole_mobility;
+    }
+
+    pub fn push_recombination(&mut self, model:Recombination)
+    {
+        self.recombination.push(model);
+    }
+
+    // sum of f(density of states, valley offset, non-parabolicity) over the conduction band valleys
+    fn sum_conduction_valleys(&self, temp:f64, f:impl Fn(f64, f64, f64) -> f64) -> f64
+    {
+        if self.conduction_valleys.is_empty()
+        {
+            return f(self.electron_properties.density_of_states(temp), 0.0, self.non_parabolicity);
+        }
+
+        self.conduction_valleys.iter().map(|valley| f(valley.density_of_states(temp), valley.energy_offset, valley.non_parabolicity)).sum()
+    }
+
+    // with the Kane dispersion the density of states to first order in alpha kT gives
+    // n = Nc (F_1/2(eta) + 15/4 alpha kT F_3/2(eta)), which reduces to the parabolic band for alpha = 0
     pub fn electron_conc(&self, fermi_lvl:f64, potential:f64, temp:f64) -> f64
     {
         let Ec_potential = self.Ec - constants::Q * potential;
-        let normalized_energy  = -(Ec_potential - fermi_lvl) / (constants::K * temp);
 
         //println!("Electron DOS {:e}, Occupied: {:e}", self.electron_properties.density_of_states(temp), fermi_dirac_half(normalized_energy));
 
-        self.electron_properties.density_of_states(temp) * fermi_dirac_half(normalized_energy)
+        self.sum_conduction_valleys(temp, |dos, energy_offset, non_parabolicity| {
+            let normalized_energy  = -(Ec_potential + energy_offset - fermi_lvl) / (constants::K * temp);
+            let correction = if non_parabolicity == 0.0 { 0.0 } else { 3.75 * non_parabolicity * constants::K * temp * fermi_dirac_3half(normalized_energy) };
+
+            dos * (fermi_dirac_half(normalized_energy) + correction)
+        })
     }
     pub fn electron_conc_derivative_pot(&self, fermi_lvl:f64, potential:f64, temp:f64) -> f64
     {
         let Ec_potential = self.Ec - constants::Q * potential;
-        let normalized_energy  = -(Ec_potential - fermi_lvl) / (constants::K * temp);
 
-        self.electron_properties.density_of_states(temp) * fermi_dirac_mhalf(normalized_energy) * -constants::Q / -(constants::K * temp)
+        self.sum_conduction_valleys(temp, |dos, energy_offset, non_parabolicity| {
+            let normalized_energy  = -(Ec_potential + energy_offset - fermi_lvl) / (constants::K * temp);
+            let correction = 3.75 * non_parabolicity * constants::K * temp * fermi_dirac_half(normalized_energy);
+
+            dos * (fermi_dirac_mhalf(normalized_energy) + correction) * -constants::Q / -(constants::K * temp)
+        })
     }
 
     pub fn electron_charge(&self, fermi_lvl:f64, potential:f64, temp:f64) -> f64
@@ -197,6 +598,46 @@ impl Bulk {
         constants::Q * self.hole_conc_derivative_pot(fermi_lvl, potential, temp)
     }
 
+    // effective band edges, defined through n = exp((Efn - Ec_eff) / kT) and p = exp((Ev_eff - Efp) / kT)
+    // they include the density of states and degeneracy, used for the Scharfetter-Gummel discretization
+    pub fn electron_effective_Ec(&self, fermi_lvl_n:f64, potential:f64, temp:f64) -> f64
+    {
+        fermi_lvl_n - constants::K * temp * self.electron_conc(fermi_lvl_n, potential, temp).ln()
+    }
+    pub fn hole_effective_Ev(&self, fermi_lvl_p:f64, potential:f64, temp:f64) -> f64
+    {
+        fermi_lvl_p + constants::K * temp * self.hole_conc(fermi_lvl_p, potential, temp).ln()
+    }
+
+    // net recombination rate and its derivatives wrt (n, p, fermi_lvl_n - fermi_lvl_p)
+    pub fn recombination_rate_derivative(&self, n:f64, p:f64, fermi_split:f64, temp:f64) -> (f64, [f64; 3])
+    {
+        recombination::net_rate_derivative(&self.recombination, self, n, p, fermi_split, temp)
+    }
+
+    // Kane band to band tunneling rate (m^-3 s^-1) and its derivative wrt the field magnitude
+    // G = a E^2 / sqrt(Eg) exp(-b Eg^1.5 / |E|), with a = sqrt(2 m_r) q^2 / (4 pi^2 hbar^2) and b = 4 sqrt(2 m_r) / (3 q hbar)
+    pub fn kane_generation_derivative(&self, field:f64) -> (f64, f64)
+    {
+        let field = field.abs();
+        let hbar = constants::PLANK_CONST / (2.0 * f64::consts::PI);
+        let electron_mass = self.electron_properties.effectiveMass;
+        let hole_mass = self.hole_properties.effectiveMass;
+        let reduced_mass = electron_mass * hole_mass / (electron_mass + hole_mass);
+
+        let a = (2.0 * reduced_mass).sqrt() * constants::Q * constants::Q / (4.0 * f64::consts::PI * f64::consts::PI * hbar * hbar);
+        let critical_field = 4.0 * (2.0 * reduced_mass).sqrt() / (3.0 * constants::Q * hbar) * self.band_gap.powf(1.5);
+
+        let generation = a * field * field / self.band_gap.sqrt() * f64::exp(-critical_field / field);
+
+        if generation == 0.0
+        {
+            return (0.0, 0.0);
+        }
+
+        (generation, generation * (2.0 / field + critical_field / (field * field)))
+    }
+
 }
 
 
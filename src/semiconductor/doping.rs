@@ -1,6 +1,9 @@
 use crate::common::*;
 use constants::Q;
 
+// field in V/m below which the Poole-Frenkel lowering is smoothed out, well below the fields where it matters
+const POOLE_FRENKEL_FIELD:f64 = 1e6;
+
 #[derive(Debug)]
 pub enum Types
 {
@@ -8,6 +11,18 @@ pub enum Types
     Donor
 }
 
+// dopant ionization models
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ionization
+{
+    // every dopant is ionized
+    Full,
+    // the dopant level is occupied following Fermi-Dirac statistics with the dopant degeneracy
+    FermiDirac,
+    // Fermi-Dirac with the ionization energy lowered by sqrt(q^3 |E| / (pi epsilon)) in the local field
+    PooleFrenkel,
+}
+
 #[derive(Debug)]
 pub struct Dopant
 {
@@ -17,6 +32,7 @@ pub struct Dopant
     pub doping_type:Types,
     pub dopantE:f64,
     pub degeneracy:f64,
+    pub ionization:Ionization,
 }
 
 impl Dopant
@@ -29,7 +45,8 @@ impl Dopant
             interp_mode,
             doping_type,
             dopantE,
-            degeneracy
+            degeneracy,
+            ionization:Ionization::Full
         }
     }
 
@@ -41,7 +58,8 @@ impl Dopant
             interp_mode,
             doping_type:Types::Donor,
             dopantE,
-            degeneracy
+            degeneracy,
+            ionization:Ionization::Full
         }
     }
     pub fn create_acceptor(sampled_conc:Vec<f64>, sampled_x:Vec<f64>, interp_mode:interp::Types, dopantE:f64, degeneracy:f64) -> Dopant
@@ -52,7 +70,8 @@ impl Dopant
             interp_mode,
             doping_type:Types::Acceptor,
             dopantE,
-            degeneracy
+            degeneracy,
+            ionization:Ionization::Full
         }
     }
 
@@ -78,6 +97,52 @@ impl Dopant
         }
     }
 
+    pub fn set_ionization(&mut self, ionization:Ionization)
+    {
+        self.ionization = ionization;
+    }
+
+    // ionized fraction and its derivatives wrt the potential and the field
+    // donors exchange electrons with the conduction band and use the electron quasi-fermi level, acceptors the hole one
+    // the dopant level moves with the potential like the band edges, the derivative wrt fermi_lvl / Q is the same
+    pub fn ionized_fraction_derivative(&self, fermi_lvl:f64, potential:f64, field:f64, epsilon:f64, temp:f64) -> (f64, f64, f64)
+    {
+        let (field_lowering, field_lowering_derivative) = match self.ionization {
+            Ionization::Full => return (1.0, 0.0, 0.0),
+            Ionization::FermiDirac => (0.0, 0.0),
+            Ionization::PooleFrenkel => {
+                // sqrt(|E|) is replaced by E^2 / (E^2 + E0^2)^3/4 so the lowering is smooth at zero field
+                let coeff = (Q * Q * Q / (std::f64::consts::PI * epsilon)).sqrt();
+                let field_sq = field * field;
+                let smooth_field = field_sq + POOLE_FRENKEL_FIELD * POOLE_FRENKEL_FIELD;
+
+                (
+                    coeff * field_sq / smooth_field.powf(0.75),
+                    coeff * field * (0.5 * field_sq + 2.0 * POOLE_FRENKEL_FIELD * POOLE_FRENKEL_FIELD) / smooth_field.powf(1.75)
+                )
+            }
+        };
+
+        let thermal_energy = constants::K * temp;
+
+        // exponent of the occupied to ionized ratio and its derivatives wrt potential and field
+        let (exponent, exponent_derivative, exponent_derivative_field) = match self.doping_type {
+            Types::Donor => ((fermi_lvl - (self.dopantE + field_lowering - Q * potential)) / thermal_energy, Q / thermal_energy, -field_lowering_derivative / thermal_energy),
+            Types::Acceptor => (((self.dopantE - field_lowering - Q * potential) - fermi_lvl) / thermal_energy, -Q / thermal_energy, -field_lowering_derivative / thermal_energy),
+        };
+
+        let ratio = self.degeneracy * f64::exp(exponent);
+        if ratio.is_infinite()
+        {
+            return (0.0, 0.0, 0.0);
+        }
+
+        let fraction = 1.0 / (1.0 + ratio);
+        let fraction_slope = -fraction * (1.0 - fraction);
+
+        (fraction, fraction_slope * exponent_derivative, fraction_slope * exponent_derivative_field)
+    }
+
     // calculate the concentration of ionized dopants
     pub fn ionized_conc(&self, x:f64, fermi_lvl:f64, temp:f64) -> f64
     {
@@ -99,6 +164,13 @@ impl Dopant
     {
         stats::fermi_dirac_derivativeF(self.dopantE, fermi_lvl, temp, self.degeneracy) * self.dopant_charge(x)
     }
-}
 
+    // charge density of ionized dopants at x and its derivatives wrt the potential and the field
+    pub fn ionized_charge_derivative_pot(&self, x:f64, fermi_lvl:f64, potential:f64, field:f64, epsilon:f64, temp:f64) -> (f64, f64, f64)
+    {
+        let charge = self.dopant_charge(x);
+        let (fraction, fraction_derivative, fraction_derivative_field) = self.ionized_fraction_derivative(fermi_lvl, potential, field, epsilon, temp);
 
+        (fraction * charge, fraction_derivative * charge, fraction_derivative_field * charge)
+    }
+}
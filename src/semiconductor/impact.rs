@@ -0,0 +1,70 @@
+// field dependent impact ionization coefficients
+#[derive(Debug, Clone, Copy)]
+pub struct IonizationCoeff
+{
+    pub a:f64,
+    pub b:f64,
+    pub exponent:f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImpactIonization
+{
+    pub electron:IonizationCoeff,
+    pub hole:IonizationCoeff,
+}
+
+impl IonizationCoeff
+{
+    // Chynoweth law, alpha = a exp(-b / |E|)
+    pub fn create_chynoweth(a:f64, b:f64) -> IonizationCoeff
+    {
+        IonizationCoeff {
+            a,
+            b,
+            exponent:1.0
+        }
+    }
+
+    // Selberherr law, alpha = a exp(-(b / |E|)^exponent)
+    pub fn create_selberherr(a:f64, b:f64, exponent:f64) -> IonizationCoeff
+    {
+        IonizationCoeff {
+            a,
+            b,
+            exponent
+        }
+    }
+
+    // ionization coefficient (1/m) and its derivative wrt the field magnitude
+    pub fn alpha_derivative(&self, field:f64) -> (f64, f64)
+    {
+        let field = field.abs();
+        let ratio = (self.b / field).powf(self.exponent);
+        let alpha = self.a * f64::exp(-ratio);
+
+        if alpha == 0.0
+        {
+            return (0.0, 0.0);
+        }
+
+        (alpha, alpha * self.exponent * ratio / field)
+    }
+}
+
+impl ImpactIonization
+{
+    pub fn create(electron:IonizationCoeff, hole:IonizationCoeff) -> ImpactIonization
+    {
+        ImpactIonization {
+            electron,
+            hole
+        }
+    }
+
+    // (alpha_n, alpha_p) at the given field
+    pub fn coeffs(&self, field:f64) -> (f64, f64)
+    {
+        (self.electron.alpha_derivative(field).0, self.hole.alpha_derivative(field).0)
+    }
+}
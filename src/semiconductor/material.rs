@@ -0,0 +1,529 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::common::*;
+use super::bulk::*;
+use super::mobility::*;
+use super::alloy::Bowing;
+
+// material library, a plain text file of sections
+//
+//   # comment
+//   [GaAs]
+//   band_gap = 1.424
+//   electron_mobility = 0.85
+//
+//   [AlGaAs]
+//   composition_range = [0.0, 0.45]
+//   band_gap = [1.424, 1.247]
+//
+// a value is either a number or a list of polynomial coefficients [c0, c1, c2, ...] in the mole fraction x,
+// value = c0 + c1 x + c2 x^2 + ..., a material can appear several times with disjoint composition ranges
+//
+// units: energies in eV, masses in electron masses, mobilities in m^2/Vs at 300 K,
+// concentrations in m^-3 and velocities in m/s
+//
+// required keys: electron_affinity, band_gap, relative_permitivity, electron_mass, hole_mass, electron_mobility, hole_mobility
+// optional keys, given per carrier with the electron_ or hole_ prefix:
+//   mobility_temp_exponent                                     lattice mobility ~ (T / 300 K)^-exponent
+//   min_mobility, mobility_ref_conc, mobility_alpha            Caughey-Thomas doping dependence
+//   saturation_velocity, saturation_beta                       velocity saturation, beta defaults to 1
+// alloys of database entries are given by their components instead of the required keys:
+//   components = [InAs, GaAs]                                  ternary A_x B_1-x C from the binaries AC and BC
+//   components = [InGaAs, InGaP, InAsP, GaAsP]                 quaternary A_x B_1-x C_y D_1-y from the ternaries ABC, ABD, ACD, BCD
+//   bowing_<required key>                                      bowing parameter of a ternary, defaults to 0
+// optional conduction band valleys, listed by name and given per valley with the name as prefix:
+//   conduction_valleys = [L, Gamma, X]
+//   L_energy_offset (eV from Ec), L_mass, L_degeneracy         degeneracy defaults to 1
+//   L_non_parabolicity (1/eV)                                  Kane non-parabolicity, defaults to 0
+// optional non_parabolicity (1/eV) of the conduction band when no valleys are given
+// optional temperature keys:
+//   varshni_alpha (eV/K), varshni_beta (K)                     Varshni band gap, the file values are at 300 K
+//   affinity_temp_coeff (eV/K)                                 linear change of the affinity, defaults to 0
+// optional valence_band_offset (eV), the valence band edge on the scale of the band offset tables
+// optional strain keys:
+//   crystal_structure = zincblende or wurtzite                 defaults to zincblende
+//   lattice_constant (angstrom), c11, c12 (GPa)                lattice and elastic constants, given together,
+//                                                              wurtzite also needs c13 and c33, a is the lattice constant
+//   deformation_conduction, deformation_valence (eV)           hydrostatic deformation potentials a_c and a_v, default to 0
+//   deformation_shear (eV)                                     shear deformation potential b, defaults to 0
+// optional polarization keys of wurtzite materials, all given together:
+//   spontaneous_polarization, piezo_e31, piezo_e33 (C/m^2)     along the growth direction, metal face values are negative
+//   c13, c33 (GPa)                                             elastic constants of the piezoelectric polarization and wurtzite strain
+#[derive(Debug)]
+pub struct Material
+{
+    pub name:String,
+    pub composition_range:(f64, f64),
+    pub parameters:HashMap<String, Vec<f64>>,
+    pub components:Vec<String>,
+    pub conduction_valleys:Vec<String>,
+    pub crystal_structure:CrystalStructure,
+}
+
+#[derive(Debug, Default)]
+pub struct MaterialDatabase
+{
+    pub materials:Vec<Material>,
+}
+
+impl Material
+{
+    pub fn create(name:&str) -> Material
+    {
+        Material {
+            name:name.to_string(),
+            composition_range:(0.0, 1.0),
+            parameters:HashMap::new(),
+            components:Vec::new(),
+            conduction_valleys:Vec::new(),
+            crystal_structure:CrystalStructure::Zincblende
+        }
+    }
+
+    pub fn has_parameter(&self, key:&str) -> bool
+    {
+        self.parameters.contains_key(key)
+    }
+
+    // parameter value at mole fraction x
+    pub fn parameter(&self, key:&str, x:f64) -> f64
+    {
+        let coeffs = self.parameters.get(key)
+            .unwrap_or_else(|| panic!("Error: material '{}' has no parameter '{}'", self.name, key));
+
+        coeffs.iter().rev().fold(0.0, |acc, c| acc * x + c)
+    }
+
+    pub fn parameter_or(&self, key:&str, x:f64, default:f64) -> f64
+    {
+        if self.has_parameter(key) { self.parameter(key, x) } else { default }
+    }
+
+    // ternary bowing parameters at mole fraction x
+    pub fn bowing(&self, x:f64) -> Bowing
+    {
+        Bowing {
+            electron_affinity:constants::from_eV(self.parameter_or("bowing_electron_affinity", x, 0.0)),
+            band_gap:constants::from_eV(self.parameter_or("bowing_band_gap", x, 0.0)),
+            relative_permitivity:self.parameter_or("bowing_relative_permitivity", x, 0.0),
+            electron_mass:self.parameter_or("bowing_electron_mass", x, 0.0) * constants::ELECTRON_MASS,
+            hole_mass:self.parameter_or("bowing_hole_mass", x, 0.0) * constants::ELECTRON_MASS,
+            electron_mobility:self.parameter_or("bowing_electron_mobility", x, 0.0),
+            hole_mobility:self.parameter_or("bowing_hole_mobility", x, 0.0),
+        }
+    }
+
+    fn mobility_model(&self, carrier:&str, x:f64) -> MobilityModel
+    {
+        let key = |name:&str| format!("{}_{}", carrier, name);
+
+        let doping = if self.has_parameter(&key("min_mobility"))
+        {
+            Some(DopingMobility {
+                min_mobility:self.parameter(&key("min_mobility"), x),
+                ref_conc:self.parameter(&key("mobility_ref_conc"), x),
+                alpha:self.parameter(&key("mobility_alpha"), x),
+            })
+        }
+        else { None };
+
+        let field = if self.has_parameter(&key("saturation_velocity"))
+        {
+            Some(FieldMobility {
+                saturation_velocity:self.parameter(&key("saturation_velocity"), x),
+                beta:self.parameter_or(&key("saturation_beta"), x, 1.0),
+            })
+        }
+        else { None };
+
+        MobilityModel::create(self.parameter_or(&key("mobility_temp_exponent"), x, 0.0), doping, field)
+    }
+
+    // build the bulk material at mole fraction x
+    pub fn create_bulk(&self, x:f64) -> Bulk
+    {
+        let hole_properties = CarrrierInfo{
+            mobility:self.parameter("hole_mobility", x),
+            effectiveMass:self.parameter("hole_mass", x) * constants::ELECTRON_MASS,
+        };
+
+        let electron_properties = CarrrierInfo{
+            mobility:self.parameter("electron_mobility", x),
+            effectiveMass:self.parameter("electron_mass", x) * constants::ELECTRON_MASS,
+        };
+
+        let mut bulk = Bulk::create(
+            constants::from_eV(self.parameter("electron_affinity", x)),
+            constants::from_eV(self.parameter("band_gap", x)),
+            self.parameter("relative_permitivity", x),
+            hole_properties,
+            electron_properties
+        );
+
+        bulk.set_mobility_models(self.mobility_model("electron", x), self.mobility_model("hole", x));
+        bulk.non_parabolicity = self.parameter_or("non_parabolicity", x, 0.0) / constants::Q;
+
+        for name in &self.conduction_valleys
+        {
+            bulk.push_conduction_valley(Valley::create_non_parabolic(
+                constants::from_eV(self.parameter(&format!("{}_energy_offset", name), x)),
+                self.parameter(&format!("{}_mass", name), x) * constants::ELECTRON_MASS,
+                self.parameter_or(&format!("{}_degeneracy", name), x, 1.0),
+                self.parameter_or(&format!("{}_non_parabolicity", name), x, 0.0) / constants::Q
+            ));
+        }
+
+        if self.has_parameter("varshni_alpha")
+        {
+            bulk.band_temperature = Some(BandTemperature::create(
+                constants::from_eV(self.parameter("varshni_alpha", x)),
+                self.parameter("varshni_beta", x),
+                constants::from_eV(self.parameter_or("affinity_temp_coeff", x, 0.0))
+            ));
+        }
+
+        if self.has_parameter("valence_band_offset")
+        {
+            bulk.valence_band_offset = Some(constants::from_eV(self.parameter("valence_band_offset", x)));
+        }
+
+        if self.has_parameter("lattice_constant")
+        {
+            let mut deformation = Deformation::create(
+                self.parameter("lattice_constant", x) * 1e-10,
+                self.parameter("c11", x) * 1e9,
+                self.parameter("c12", x) * 1e9,
+                constants::from_eV(self.parameter_or("deformation_conduction", x, 0.0)),
+                constants::from_eV(self.parameter_or("deformation_valence", x, 0.0)),
+                constants::from_eV(self.parameter_or("deformation_shear", x, 0.0))
+            );
+
+            if self.crystal_structure == CrystalStructure::Wurtzite
+            {
+                deformation.set_wurtzite(self.parameter("c13", x) * 1e9, self.parameter("c33", x) * 1e9);
+            }
+
+            bulk.deformation = Some(deformation);
+        }
+
+        if self.has_parameter("spontaneous_polarization")
+        {
+            bulk.polarization = Some(Polarization::create(
+                self.parameter("spontaneous_polarization", x),
+                self.parameter("piezo_e31", x),
+                self.parameter("piezo_e33", x),
+                self.parameter("c13", x) * 1e9,
+                self.parameter("c33", x) * 1e9
+            ));
+        }
+
+        bulk
+    }
+}
+
+fn parse_names(value:&str, line_no:usize) -> Vec<String>
+{
+    let names = value.strip_prefix('[').and_then(|v| v.strip_suffix(']'))
+        .unwrap_or_else(|| panic!("Error: expected a list of names at line {}", line_no));
+
+    names.split(',').map(|n| n.trim().to_string()).filter(|n| !n.is_empty()).collect()
+}
+
+fn parse_value(value:&str, line_no:usize) -> Vec<f64>
+{
+    let parse_number = |s:&str| s.trim().parse::<f64>()
+        .unwrap_or_else(|_| panic!("Error: invalid number '{}' in material file at line {}", s.trim(), line_no));
+
+    match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        Some(list) => list.split(',').filter(|s| !s.trim().is_empty()).map(parse_number).collect(),
+        None => vec![parse_number(value)],
+    }
+}
+
+impl MaterialDatabase
+{
+    pub fn create() -> MaterialDatabase
+    {
+        MaterialDatabase {
+            materials:Vec::new()
+        }
+    }
+
+    pub fn load(filename:&str) -> MaterialDatabase
+    {
+        let contents = fs::read_to_string(filename)
+            .unwrap_or_else(|_| panic!("Error: unable to read material file '{}'", filename));
+
+        MaterialDatabase::parse(&contents)
+    }
+
+    pub fn parse(contents:&str) -> MaterialDatabase
+    {
+        let mut database = MaterialDatabase::create();
+
+        for (i, line) in contents.lines().enumerate()
+        {
+            let line_no = i + 1;
+            let line = line.split('#').next().unwrap().trim();
+
+            if line.is_empty()
+            {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']'))
+            {
+                database.materials.push(Material::create(name.trim()));
+                continue;
+            }
+
+            let (key, value) = line.split_once('=')
+                .unwrap_or_else(|| panic!("Error: expected 'key = value' in material file at line {}", line_no));
+            let material = database.materials.last_mut()
+                .unwrap_or_else(|| panic!("Error: parameter outside of a material section at line {}", line_no));
+
+            let key = key.trim();
+
+            if key == "components"
+            {
+                material.components = parse_names(value.trim(), line_no);
+                continue;
+            }
+            if key == "conduction_valleys"
+            {
+                material.conduction_valleys = parse_names(value.trim(), line_no);
+                continue;
+            }
+            if key == "crystal_structure"
+            {
+                material.crystal_structure = match value.trim() {
+                    "zincblende" => CrystalStructure::Zincblende,
+                    "wurtzite" => CrystalStructure::Wurtzite,
+                    other => panic!("Error: unknown crystal structure '{}' at line {}", other, line_no),
+                };
+                continue;
+            }
+
+            let value = parse_value(value.trim(), line_no);
+
+            if key == "composition_range"
+            {
+                if value.len() != 2 { panic!("Error: composition_range needs two values at line {}", line_no); }
+                material.composition_range = (value[0], value[1]);
+            }
+            else
+            {
+                material.parameters.insert(key.to_string(), value);
+            }
+        }
+
+        database
+    }
+
+    pub fn push_material(&mut self, material:Material)
+    {
+        self.materials.push(material);
+    }
+
+    // find the entry of the material covering the mole fraction x
+    pub fn find(&self, name:&str, x:f64) -> Option<&Material>
+    {
+        self.materials.iter().find(|m| m.name == name && x >= m.composition_range.0 && x <= m.composition_range.1)
+    }
+
+    // build a binary or elemental material by name
+    pub fn create_bulk(&self, name:&str) -> Bulk
+    {
+        self.create_alloy_bulk(name, 0.0)
+    }
+
+    fn find_or_panic(&self, name:&str, x:f64) -> &Material
+    {
+        self.find(name, x)
+            .unwrap_or_else(|| panic!("Error: no material '{}' with mole fraction {} in the database", name, x))
+    }
+
+    // build an alloy by name at mole fraction x
+    pub fn create_alloy_bulk(&self, name:&str, x:f64) -> Bulk
+    {
+        let material = self.find_or_panic(name, x);
+
+        match material.components.len() {
+            0 => material.create_bulk(x),
+            2 => Bulk::create_ternary(
+                &self.create_bulk(&material.components[0]), 
+                &self.create_bulk(&material.components[1]), 
+                x, 
+                &material.bowing(x)
+            ),
+            _ => panic!("Error: material '{}' is not a ternary", name),
+        }
+    }
+
+    // build a quaternary A_x B_1-x C_y D_1-y by name, its components are the ternaries ABC, ABD, ACD and BCD
+    pub fn create_quaternary_bulk(&self, name:&str, x:f64, y:f64) -> Bulk
+    {
+        let material = self.find_or_panic(name, x);
+
+        if material.components.len() != 4
+        {
+            panic!("Error: material '{}' is not a quaternary", name);
+        }
+
+        let ternaries = [0, 1, 2, 3].map(|k| self.find_or_panic(&material.components[k], if k < 2 { x } else { y }));
+        let bowing = [0, 1, 2, 3].map(|k| ternaries[k].bowing(if k < 2 { x } else { y }));
+
+        if ternaries.iter().any(|t| t.components.len() != 2)
+        {
+            panic!("Error: the components of '{}' must be ternaries", name);
+        }
+
+        Bulk::create_quaternary(
+            &self.create_bulk(&ternaries[0].components[0]),
+            &self.create_bulk(&ternaries[1].components[0]),
+            &self.create_bulk(&ternaries[0].components[1]),
+            &self.create_bulk(&ternaries[1].components[1]),
+            x,
+            y,
+            [&bowing[0], &bowing[1], &bowing[2], &bowing[3]]
+        )
+    }
+
+    // build an alloy by name at mole fraction x and temperature temp
+    pub fn create_alloy_bulk_at_temp(&self, name:&str, x:f64, temp:f64) -> Bulk
+    {
+        let mut bulk = self.create_alloy_bulk(name, x);
+        bulk.set_temperature(temp);
+        bulk
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const TEST_FILE:&str = "
+# test materials
+[GaAs]
+electron_affinity = 4.07   # trailing comment
+band_gap = 1.424
+relative_permitivity = 12.9
+electron_mass = 0.063
+hole_mass = 0.51
+electron_mobility = 0.85
+hole_mobility = 0.04
+
+[AlGaAs]
+composition_range = [0.0, 0.45]
+band_gap = [1.424, 1.247]
+
+[AlGaAs]
+composition_range = [0.45, 1.0]
+band_gap = [1.9, 0.125, 0.143]
+
+[InGaAs]
+components = [InAs, GaAs]
+";
+
+    #[test]
+    fn parse_sections_and_lists()
+    {
+        let database = MaterialDatabase::parse(TEST_FILE);
+
+        assert_eq!(database.materials.len(), 4);
+
+        let gaas = database.find("GaAs", 0.0).unwrap();
+        assert_eq!(gaas.parameters["electron_affinity"], vec![4.07]);
+        assert_eq!(gaas.composition_range, (0.0, 1.0));
+        assert_eq!(gaas.crystal_structure, CrystalStructure::Zincblende);
+
+        let indirect = database.find("AlGaAs", 0.8).unwrap();
+        assert_eq!(indirect.composition_range, (0.45, 1.0));
+        assert!((indirect.parameter("band_gap", 0.8) - (1.9 + 0.125*0.8 + 0.143*0.64)).abs() < 1e-12);
+
+        assert_eq!(database.find("InGaAs", 0.5).unwrap().components, vec!["InAs".to_string(), "GaAs".to_string()]);
+        assert!(database.find("AlGaAs", 1.2).is_none());
+        assert!(database.find("InP", 0.0).is_none());
+    }
+
+    #[test]
+    fn find_picks_the_first_range_at_a_shared_bound()
+    {
+        let database = MaterialDatabase::parse(TEST_FILE);
+
+        assert_eq!(database.find("AlGaAs", 0.45).unwrap().composition_range, (0.0, 0.45));
+    }
+
+    // the direct and indirect entries describe the same alloy at the crossover of the gamma and X gaps
+    #[test]
+    fn AlGaAs_entries_match_at_the_crossover()
+    {
+        let database = MaterialDatabase::parse(include_str!("../../materials/materials.txt"));
+        let x = 0.45;
+        let entries:Vec<&Material> = database.materials.iter().filter(|m| m.name == "AlGaAs").collect();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].composition_range.1, x);
+        assert_eq!(entries[1].composition_range.0, x);
+
+        let (direct, indirect) = (entries[0], entries[1]);
+        let valley_edge = |m:&Material, name:&str| -m.parameter("electron_affinity", x) + m.parameter(&format!("{}_energy_offset", name), x);
+
+        assert!((direct.parameter("band_gap", x) - indirect.parameter("band_gap", x)).abs() < 1e-3);
+        assert!((direct.parameter("electron_affinity", x) - indirect.parameter("electron_affinity", x)).abs() < 3e-3);
+        assert!((valley_edge(direct, "Gamma") - valley_edge(indirect, "Gamma")).abs() < 3e-3);
+        assert!((valley_edge(direct, "X") - valley_edge(indirect, "X")).abs() < 3e-3);
+        assert_eq!(direct.parameter("valence_band_offset", x), indirect.parameter("valence_band_offset", x));
+    }
+
+    #[test]
+    #[should_panic(expected = "expected 'key = value' in material file at line 3")]
+    fn parse_rejects_a_line_without_value()
+    {
+        MaterialDatabase::parse("[GaAs]\nband_gap = 1.424\nband_gap\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "parameter outside of a material section at line 1")]
+    fn parse_rejects_a_parameter_before_a_section()
+    {
+        MaterialDatabase::parse("band_gap = 1.424\n[GaAs]\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid number '1.4.2' in material file at line 2")]
+    fn parse_rejects_an_invalid_number()
+    {
+        MaterialDatabase::parse("[GaAs]\nband_gap = [1.4.2, 0.1]\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "composition_range needs two values at line 2")]
+    fn parse_rejects_a_composition_range_without_two_values()
+    {
+        MaterialDatabase::parse("[AlGaAs]\ncomposition_range = [0.0, 0.45, 1.0]\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a list of names at line 2")]
+    fn parse_rejects_components_without_a_list()
+    {
+        MaterialDatabase::parse("[InGaAs]\ncomponents = InAs, GaAs\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown crystal structure 'rocksalt' at line 2")]
+    fn parse_rejects_an_unknown_crystal_structure()
+    {
+        MaterialDatabase::parse("[PbTe]\ncrystal_structure = rocksalt\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "material 'AlGaAs' has no parameter 'electron_affinity'")]
+    fn parameter_rejects_a_missing_key()
+    {
+        MaterialDatabase::parse(TEST_FILE).find("AlGaAs", 0.2).unwrap().parameter("electron_affinity", 0.2);
+    }
+}
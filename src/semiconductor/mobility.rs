@@ -0,0 +1,75 @@
+// Caughey-Thomas doping dependence, mu = min_mobility + (mu_lattice - min_mobility) / (1 + (N / ref_conc)^alpha)
+// N is the total (donor + acceptor) dopant concentration
+#[derive(Debug, Clone, Copy)]
+pub struct DopingMobility
+{
+    pub min_mobility:f64,
+    pub ref_conc:f64,
+    pub alpha:f64,
+}
+
+// high field velocity saturation, mu = mu_low / (1 + (mu_low |E| / saturation_velocity)^beta)^(1 / beta)
+#[derive(Debug, Clone, Copy)]
+pub struct FieldMobility
+{
+    pub saturation_velocity:f64,
+    pub beta:f64,
+}
+
+// mobility model of a carrier, the default is a constant mobility
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MobilityModel
+{
+    // the lattice mobility scales as (T / 300 K)^-temp_exponent
+    pub temp_exponent:f64,
+    pub doping:Option<DopingMobility>,
+    pub field:Option<FieldMobility>,
+}
+
+impl MobilityModel
+{
+    pub fn create(temp_exponent:f64, doping:Option<DopingMobility>, field:Option<FieldMobility>) -> MobilityModel
+    {
+        MobilityModel {
+            temp_exponent,
+            doping,
+            field
+        }
+    }
+
+    // low field mobility from the 300 K lattice mobility
+    pub fn low_field_mobility(&self, mobility_300K:f64, dopant_conc:f64, temp:f64) -> f64
+    {
+        let lattice_mobility = mobility_300K * (temp / 300.0).powf(-self.temp_exponent);
+
+        match &self.doping {
+            Some(doping) => doping.min_mobility + (lattice_mobility - doping.min_mobility) / (1.0 + (dopant_conc / doping.ref_conc).powf(doping.alpha)),
+            None => lattice_mobility,
+        }
+    }
+
+    // mobility and its derivative wrt the field magnitude
+    pub fn mobility_derivative(&self, mobility_300K:f64, dopant_conc:f64, field:f64, temp:f64) -> (f64, f64)
+    {
+        let low_field_mobility = self.low_field_mobility(mobility_300K, dopant_conc, temp);
+
+        let field_model = match &self.field {
+            Some(field_model) => field_model,
+            None => return (low_field_mobility, 0.0),
+        };
+
+        let ratio = low_field_mobility * field.abs() / field_model.saturation_velocity;
+        let denominator = 1.0 + ratio.powf(field_model.beta);
+        let mobility = low_field_mobility * denominator.powf(-1.0 / field_model.beta);
+
+        if ratio == 0.0
+        {
+            let derivative = if field_model.beta == 1.0 { -low_field_mobility * low_field_mobility / field_model.saturation_velocity } else { 0.0 };
+            return (mobility, derivative);
+        }
+
+        let derivative = -mobility / denominator * ratio.powf(field_model.beta) / field.abs();
+
+        (mobility, derivative)
+    }
+}
@@ -2,8 +2,24 @@
 pub mod doping;
 pub mod bulk;
 pub mod semiconductor;
+pub mod recombination;
+pub mod impact;
+pub mod tunneling;
+pub mod mobility;
+pub mod material;
+pub mod alloy;
+pub mod narrowing;
+pub mod trap;
 
 pub use doping::*;
 pub use bulk::*;
 pub use semiconductor::*;
+pub use recombination::Recombination;
+pub use impact::*;
+pub use tunneling::BandToBand;
+pub use mobility::*;
+pub use material::{Material, MaterialDatabase};
+pub use alloy::Bowing;
+pub use narrowing::BandGapNarrowing;
+pub use trap::*;
 
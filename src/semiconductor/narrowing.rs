@@ -0,0 +1,56 @@
+// band gap narrowing from heavy doping, the shifts lower Ec and raise Ev
+#[derive(Debug, Clone, Copy)]
+pub enum BandGapNarrowing
+{
+    // Slotboom, dEg = energy (ln(N / ref_conc) + sqrt(ln(N / ref_conc)^2 + 0.5)) with N the total dopant concentration,
+    // conduction_fraction of dEg lowers Ec and the rest raises Ev
+    // silicon: energy = 9 meV, ref_conc = 1e23 m^-3
+    Slotboom{ energy:f64, ref_conc:f64, conduction_fraction:f64 },
+    // Jain-Roulston, coefficients [A, B, C] for n and p type material with N the majority dopant concentration
+    // in units of 1e24 m^-3, the majority band moves by A N^1/3 + C N^1/2 and the minority band by B N^1/4
+    JainRoulston{ n_type:[f64; 3], p_type:[f64; 3] },
+}
+
+const JAIN_ROULSTON_REF_CONC:f64 = 1e24;
+
+impl BandGapNarrowing
+{
+    pub fn create_slotboom(energy:f64, ref_conc:f64, conduction_fraction:f64) -> BandGapNarrowing
+    {
+        BandGapNarrowing::Slotboom{ energy, ref_conc, conduction_fraction }
+    }
+
+    pub fn create_jain_roulston(n_type:[f64; 3], p_type:[f64; 3]) -> BandGapNarrowing
+    {
+        BandGapNarrowing::JainRoulston{ n_type, p_type }
+    }
+
+    // (lowering of Ec, raising of Ev) for the local donor and acceptor concentrations
+    pub fn band_edge_shift(&self, donor_conc:f64, acceptor_conc:f64) -> (f64, f64)
+    {
+        match *self {
+            BandGapNarrowing::Slotboom{ energy, ref_conc, conduction_fraction } => {
+                let total_conc = donor_conc + acceptor_conc;
+
+                if total_conc <= 0.0
+                {
+                    return (0.0, 0.0);
+                }
+
+                let log_ratio = (total_conc / ref_conc).ln();
+                let narrowing = energy * (log_ratio + (log_ratio * log_ratio + 0.5).sqrt());
+
+                (conduction_fraction * narrowing, (1.0 - conduction_fraction) * narrowing)
+            }
+            BandGapNarrowing::JainRoulston{ n_type, p_type } => {
+                let (coeffs, majority_conc) = if donor_conc >= acceptor_conc { (n_type, donor_conc) } else { (p_type, acceptor_conc) };
+                let normalized_conc = majority_conc / JAIN_ROULSTON_REF_CONC;
+
+                let majority_shift = coeffs[0] * normalized_conc.powf(1.0 / 3.0) + coeffs[2] * normalized_conc.sqrt();
+                let minority_shift = coeffs[1] * normalized_conc.powf(0.25);
+
+                if donor_conc >= acceptor_conc { (majority_shift, minority_shift) } else { (minority_shift, majority_shift) }
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use crate::common::*;
+use super::bulk::Bulk;
+
+// recombination processes of a bulk material
+// every model has the form R = (np - ni^2) * factor(n, p), the total factor is the sum over the models
+#[derive(Debug, Clone, Copy)]
+pub enum Recombination
+{
+    // Shockley-Read-Hall through a single trap level, trap_energy is measured from the midgap
+    SRH{ electron_lifetime:f64, hole_lifetime:f64, trap_energy:f64 },
+    // band to band Auger, factor = Cn * n + Cp * p
+    Auger{ electron_coeff:f64, hole_coeff:f64 },
+    // radiative band to band, factor = B
+    Radiative{ coeff:f64 },
+}
+
+impl Recombination
+{
+    pub fn create_SRH(electron_lifetime:f64, hole_lifetime:f64, trap_energy:f64) -> Recombination
+    {
+        Recombination::SRH{ electron_lifetime, hole_lifetime, trap_energy }
+    }
+
+    pub fn create_auger(electron_coeff:f64, hole_coeff:f64) -> Recombination
+    {
+        Recombination::Auger{ electron_coeff, hole_coeff }
+    }
+
+    pub fn create_radiative(coeff:f64) -> Recombination
+    {
+        Recombination::Radiative{ coeff }
+    }
+
+    // recombination factor and its derivatives wrt (n, p)
+    pub fn factor_derivative(&self, bulk:&Bulk, n:f64, p:f64, temp:f64) -> (f64, [f64; 2])
+    {
+        match *self {
+            Recombination::SRH{ electron_lifetime, hole_lifetime, trap_energy } => {
+                // carrier concentrations with the fermi level at the trap level
+                let trap_lvl = 0.5 * (bulk.Ec + bulk.Ev) + trap_energy;
+                let n1 = bulk.electron_conc(trap_lvl, 0.0, temp);
+                let p1 = bulk.hole_conc(trap_lvl, 0.0, temp);
+
+                let denominator = hole_lifetime * (n + n1) + electron_lifetime * (p + p1);
+                let factor = 1.0 / denominator;
+
+                (factor, [-hole_lifetime * factor * factor, -electron_lifetime * factor * factor])
+            }
+            Recombination::Auger{ electron_coeff, hole_coeff } => (electron_coeff * n + hole_coeff * p, [electron_coeff, hole_coeff]),
+            Recombination::Radiative{ coeff } => (coeff, [0.0, 0.0]),
+        }
+    }
+}
+
+// effective intrinsic concentration squared ni^2 = np exp(-(fermi_lvl_n - fermi_lvl_p) / kT), which keeps the rates zero
+// at equilibrium for degenerate carriers, returned as (ni^2, ni^2 / n, ni^2 / p) and evaluated in log space
+// since the exponential overflows under strong reverse bias
+pub fn effective_intrinsic_sq(n:f64, p:f64, fermi_split:f64, temp:f64) -> (f64, f64, f64)
+{
+    let thermal_energy = constants::K * temp;
+
+    let ni_sq_per_n = f64::exp(p.ln() - fermi_split / thermal_energy);
+    let ni_sq_per_p = f64::exp(n.ln() - fermi_split / thermal_energy);
+
+    (n * ni_sq_per_n, ni_sq_per_n, ni_sq_per_p)
+}
+
+// total recombination factor of the models and its derivatives wrt (n, p)
+pub fn total_factor_derivative(models:&[Recombination], bulk:&Bulk, n:f64, p:f64, temp:f64) -> (f64, [f64; 2])
+{
+    models.iter()
+        .map(|model| model.factor_derivative(bulk, n, p, temp))
+        .fold((0.0, [0.0; 2]), |acc, f| (acc.0 + f.0, [acc.1[0] + f.1[0], acc.1[1] + f.1[1]]))
+}
+
+// net recombination rate (m^-3 s^-1) and its derivatives wrt (n, p, fermi_lvl_n - fermi_lvl_p)
+pub fn net_rate_derivative(models:&[Recombination], bulk:&Bulk, n:f64, p:f64, fermi_split:f64, temp:f64) -> (f64, [f64; 3])
+{
+    if models.is_empty()
+    {
+        return (0.0, [0.0; 3]);
+    }
+
+    net_rate_from_factor(total_factor_derivative(models, bulk, n, p, temp), n, p, fermi_split, temp)
+}
+
+// net recombination rate (np - ni^2) * factor from a factor and its derivatives wrt (n, p)
+pub fn net_rate_from_factor((factor, factor_derivative):(f64, [f64; 2]), n:f64, p:f64, fermi_split:f64, temp:f64) -> (f64, [f64; 3])
+{
+    let thermal_energy = constants::K * temp;
+
+    let (ni_sq, ni_sq_per_n, ni_sq_per_p) = effective_intrinsic_sq(n, p, fermi_split, temp);
+    let excess = n * p - ni_sq;
+
+    (
+        excess * factor,
+        [
+            (p - ni_sq_per_n) * factor + excess * factor_derivative[0],
+            (n - ni_sq_per_p) * factor + excess * factor_derivative[1],
+            ni_sq / thermal_energy * factor
+        ]
+    )
+}
@@ -2,16 +2,49 @@ use core::f64;
 
 use crate::common::*;
 use crate::fdm1D::Mesh;
+use crate::fdm1D::continuity;
 use super::bulk::*;
+use super::tunneling::{self, BandToBand};
 use super::doping::*;
+use super::trap::*;
+use super::recombination;
 
+// field at node i from the central difference of the potential, one sided at the ends
+pub fn node_field(mesh:&Mesh, potential:&VecD, i:usize) -> f64
+{
+    let prev = if i > 0 { i - 1 } else { i };
+    let next = if i < mesh.lastIdx() { i + 1 } else { i };
+
+    -(potential[next] - potential[prev]) / (mesh.points[next] - mesh.points[prev])
+}
+
+// bulk parameters as a function of the position from the start of the layer, e.g. an alloy with a graded mole fraction
+pub struct Grading(Box<dyn Fn(f64) -> Bulk>);
+
+impl std::fmt::Debug for Grading
+{
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "Grading")
+    }
+}
+
+// a graded layer keeps the bulk at its start in bulk, the local bulks are sampled at the mesh nodes when
+// the layer is pushed to a device and every routine at a position x uses the one at the nearest node
 #[derive(Debug)]
 pub struct Semiconductor
 {
     pub bulk:Bulk,
     pub dopants:Vec<Dopant>,
+    pub traps:Vec<Trap>,
+    grading:Option<Grading>,
+    graded_bulks:Vec<(f64, Bulk)>,
+    // aligned (Ec, Ev) before the layer, kept to align the graded bulks when they are sampled again
+    alignment_reference:Option<(f64, f64)>,
     begin_pos:f64,
     end_pos:f64,
+    // a layer that follows another one leaves the node at begin_pos to it
+    includes_begin:bool,
 }
 
 impl Semiconductor {
@@ -22,19 +55,116 @@ impl Semiconductor {
             bulk,
             begin_pos:-f64::INFINITY,
             end_pos:f64::INFINITY,
-            dopants:Vec::new()
+            includes_begin:true,
+            dopants:Vec::new(),
+            traps:Vec::new(),
+            grading:None,
+            graded_bulks:Vec::new(),
+            alignment_reference:None
         }
     }
 
-    pub fn set_bulk_range(&mut self, begin_pos:f64, end_pos:f64)
+    pub fn create_graded(grading:impl Fn(f64) -> Bulk + 'static) -> Semiconductor
     {
+        let mut layer = Semiconductor::create(grading(0.0));
+        layer.grading = Some(Grading(Box::new(grading)));
+
+        layer
+    }
+
+    pub fn is_graded(&self) -> bool
+    {
+        self.grading.is_some()
+    }
+
+    // sample the grading at the nodes of the layer, does nothing for uniform layers
+    pub fn sample_grading(&mut self, points:&[f64])
+    {
+        let grading = match &self.grading {
+            Some(grading) => grading,
+            None => return,
+        };
+
+        // the positions are clamped to the layer so that rounding of the mesh does not leave it
+        let width = self.end_pos - self.begin_pos;
+        self.graded_bulks = points.iter().map(|&x| (x, self.graded_bulk(grading, (x - self.begin_pos).clamp(0.0, width)))).collect();
+    }
+
+    // bulk of the grading at a position from the start of the layer, at the temperature of the layer
+    fn graded_bulk(&self, grading:&Grading, position:f64) -> Bulk
+    {
+        let mut bulk = (grading.0)(position);
+        bulk.set_temperature(self.bulk.temp);
+        bulk
+    }
+
+    // bulk at x, the one of the nearest sampled node in graded layers
+    pub fn bulk_at(&self, x:f64) -> &Bulk
+    {
+        if self.graded_bulks.is_empty()
+        {
+            return &self.bulk;
+        }
+
+        let idx = self.graded_bulks.partition_point(|(pos, _)| *pos < x).min(self.graded_bulks.len() - 1);
+
+        if idx > 0 && x - self.graded_bulks[idx - 1].0 < self.graded_bulks[idx].0 - x
+        {
+            &self.graded_bulks[idx - 1].1
+        }
+        else
+        {
+            &self.graded_bulks[idx].1
+        }
+    }
+
+    pub fn set_bulk_range(&mut self, begin_pos:f64, end_pos:f64, includes_begin:bool)
+    {
+        self.includes_begin = includes_begin;
         self.begin_pos = begin_pos;
         self.end_pos = end_pos;
     }
 
+    pub fn range(&self) -> (f64, f64)
+    {
+        (self.begin_pos, self.end_pos)
+    }
+
     pub fn is_inside(&self, x:f64) -> bool
     {
-        x >= self.begin_pos && x <= self.end_pos
+        (x > self.begin_pos || (self.includes_begin && x == self.begin_pos)) && x <= self.end_pos
+    }
+
+    // donor and acceptor concentrations at x
+    pub fn donor_acceptor_conc(&self, x:f64) -> (f64, f64)
+    {
+        self.dopants.iter().fold((0.0, 0.0), |acc, dopant| match dopant.doping_type {
+            Types::Donor => (acc.0 + dopant.dopant_conc(x), acc.1),
+            Types::Acceptor => (acc.0, acc.1 + dopant.dopant_conc(x)),
+        })
+    }
+
+    // band gap narrowing at x as (lowering of Ec, raising of Ev)
+    pub fn band_edge_shift(&self, x:f64) -> (f64, f64)
+    {
+        match &self.bulk_at(x).band_gap_narrowing {
+            Some(model) => {
+                let (donor_conc, acceptor_conc) = self.donor_acceptor_conc(x);
+                model.band_edge_shift(donor_conc, acceptor_conc)
+            }
+            None => (0.0, 0.0),
+        }
+    }
+
+    // potentials seen by the electrons and holes, band gap narrowing enters as a local shift of the potential
+    fn electron_potential(&self, x:f64, potential:f64) -> f64
+    {
+        if self.bulk_at(x).band_gap_narrowing.is_none() { potential } else { potential + self.band_edge_shift(x).0 / constants::Q }
+    }
+
+    fn hole_potential(&self, x:f64, potential:f64) -> f64
+    {
+        if self.bulk_at(x).band_gap_narrowing.is_none() { potential } else { potential - self.band_edge_shift(x).1 / constants::Q }
     }
 
     pub fn electron_conc(&self, x:f64, fermi_lvl:f64, potential:f64, temp:f64) -> f64
@@ -44,7 +174,7 @@ impl Semiconductor {
             return 0.0
         }
 
-        self.bulk.electron_conc(fermi_lvl, potential, temp)
+        self.bulk_at(x).electron_conc(fermi_lvl, self.electron_potential(x, potential), temp)
     }
 
     pub fn hole_conc(&self, x:f64, fermi_lvl:f64, potential:f64, temp:f64) -> f64
@@ -54,7 +184,7 @@ impl Semiconductor {
             return 0.0
         }
 
-        self.bulk.hole_conc(fermi_lvl, potential, temp)
+        self.bulk_at(x).hole_conc(fermi_lvl, self.hole_potential(x, potential), temp)
     }
 
     pub fn push_dopant(&mut self, dopant:Dopant)
@@ -62,52 +192,675 @@ impl Semiconductor {
         self.dopants.push(dopant);
     }
 
+    // move the layer to temp, the dopant and trap levels follow the band edges they were created from
+    // graded bulks are moved when they are sampled
+    pub fn set_temperature(&mut self, temp:f64)
+    {
+        let (Ec, Ev) = (self.bulk.Ec, self.bulk.Ev);
+        self.bulk.set_temperature(temp);
+
+        self.shift_levels(self.bulk.Ec - Ec, self.bulk.Ev - Ev);
+    }
+
+    // strain the bulk for pseudomorphic growth on a substrate, dopant levels move with their band edges
+    // and trap levels with the midgap, returns the shifts (conduction, heavy hole, light hole) of the bulk
+    // graded layers strain every sampled bulk, so sample the grading first
+    pub fn apply_strain(&mut self, substrate_lattice_constant:f64) -> (f64, f64, f64)
+    {
+        let (Ec, Ev) = (self.bulk.Ec, self.bulk.Ev);
+        let shift = self.bulk.apply_strain(substrate_lattice_constant);
+
+        self.strain_graded(substrate_lattice_constant);
+        self.shift_levels(self.bulk.Ec - Ec, self.bulk.Ev - Ev);
+
+        shift
+    }
+
+    fn strain_graded(&mut self, substrate_lattice_constant:f64)
+    {
+        for (_, bulk) in self.graded_bulks.iter_mut()
+        {
+            bulk.apply_strain(substrate_lattice_constant);
+        }
+    }
+
+    // sample the grading at new nodes, aligned and strained like the layer was when it was pushed
+    pub fn resample_grading(&mut self, points:&[f64], shift:impl Fn(Option<(f64, f64)>, &Bulk) -> f64, substrate_lattice_constant:Option<f64>)
+    {
+        self.sample_grading(points);
+        self.align_graded(&shift);
+
+        if let Some(lattice_constant) = substrate_lattice_constant
+        {
+            self.strain_graded(lattice_constant);
+        }
+    }
+
+    // shift the bands of the layer rigidly to align them to the previous layer, shift gives the energy shift of a bulk
+    // from the aligned (Ec, Ev) before it, None for the first layer, graded layers align every node to the one before
+    // returns the aligned (Ec, Ev) at the end of the layer
+    pub fn align_bands(&mut self, previous_edges:Option<(f64, f64)>, shift:impl Fn(Option<(f64, f64)>, &Bulk) -> f64) -> (f64, f64)
+    {
+        let bulk_shift = shift(previous_edges, &self.bulk);
+
+        self.alignment_reference = previous_edges;
+        self.bulk.shift_bands(bulk_shift);
+        self.shift_levels(bulk_shift, bulk_shift);
+
+        self.align_graded(&shift).unwrap_or((self.bulk.Ec, self.bulk.Ev))
+    }
+
+    // align the graded bulks node by node from the start of the layer, returns the aligned (Ec, Ev) at the last node
+    fn align_graded(&mut self, shift:&impl Fn(Option<(f64, f64)>, &Bulk) -> f64) -> Option<(f64, f64)>
+    {
+        let mut begin_bulk = self.graded_bulk(self.grading.as_ref()?, 0.0);
+        begin_bulk.shift_bands(shift(self.alignment_reference, &begin_bulk));
+
+        let mut edges = (begin_bulk.Ec, begin_bulk.Ev);
+
+        for (_, bulk) in self.graded_bulks.iter_mut()
+        {
+            bulk.shift_bands(shift(Some(edges), bulk));
+            edges = (bulk.Ec, bulk.Ev);
+        }
+
+        Some(edges)
+    }
+
+    // move the dopant levels with their band edges and the trap levels with the midgap
+    fn shift_levels(&mut self, Ec_shift:f64, Ev_shift:f64)
+    {
+        let midgap_shift = 0.5 * (Ec_shift + Ev_shift);
+
+        for dopant in self.dopants.iter_mut()
+        {
+            dopant.dopantE += match dopant.doping_type {
+                Types::Donor => Ec_shift,
+                Types::Acceptor => Ev_shift,
+            };
+        }
+
+        for trap in self.traps.iter_mut()
+        {
+            trap.trapE += midgap_shift;
+
+            if let TrapType::Amphoteric{ acceptorE } = &mut trap.trap_type
+            {
+                *acceptorE += midgap_shift;
+            }
+        }
+    }
+
+    pub fn push_trap(&mut self, trap:Trap)
+    {
+        self.traps.push(trap);
+    }
+
     pub fn total_dopant_charge_vec(&self, mesh:&Mesh) -> VecD
     {
         mesh.makeVecFn(|x, _| if self.is_inside(x) {self.dopants.iter().map(|d| d.dopant_charge(x)).sum::<f64>()} else { 0.0 })
     }
 
+    // total (donor + acceptor) dopant concentration at x
+    pub fn total_dopant_conc(&self, x:f64) -> f64
+    {
+        self.dopants.iter().map(|d| d.dopant_conc(x)).sum()
+    }
+
+    // electron mobility at x and its derivative wrt the field magnitude
+    pub fn electron_mobility_derivative(&self, x:f64, field:f64, temp:f64) -> (f64, f64)
+    {
+        self.bulk_at(x).electron_mobility.mobility_derivative(self.bulk_at(x).electron_properties.mobility, self.total_dopant_conc(x), field, temp)
+    }
+
+    // hole mobility at x and its derivative wrt the field magnitude
+    pub fn hole_mobility_derivative(&self, x:f64, field:f64, temp:f64) -> (f64, f64)
+    {
+        self.bulk_at(x).hole_mobility.mobility_derivative(self.bulk_at(x).hole_properties.mobility, self.total_dopant_conc(x), field, temp)
+    }
+
+    // electron and hole mobility per node, the field is taken from central differences
+    pub fn mobility_vec(&self, mesh:&Mesh, potential:&VecD, temp:f64) -> (VecD, VecD)
+    {
+        let mut mobility_n = mesh.zeroVec();
+        let mut mobility_p = mesh.zeroVec();
+
+        for i in 0..mesh.len()
+        {
+            if !self.is_inside(mesh.points[i])
+            {
+                continue;
+            }
+
+            let field = node_field(mesh, potential, i);
+
+            mobility_n[i] = self.electron_mobility_derivative(mesh.points[i], field, temp).0;
+            mobility_p[i] = self.hole_mobility_derivative(mesh.points[i], field, temp).0;
+        }
+
+        (mobility_n, mobility_p)
+    }
+
     pub fn total_charge(&self, x:f64, fermi_lvl:f64, potential:f64, temp:f64) -> f64
+    {
+        self.total_charge_qf(x, fermi_lvl, fermi_lvl, potential, temp)
+    }
+
+    pub fn total_charge_derivative_pot(&self, x:f64, fermi_lvl:f64, potential:f64, temp:f64) -> f64
+    {
+        self.total_charge_derivative_pot_qf(x, fermi_lvl, fermi_lvl, potential, temp)
+    }
+
+    // charge with separate quasi-fermi levels for electrons and holes, without field enhanced ionization
+    pub fn total_charge_qf(&self, x:f64, fermi_lvl_n:f64, fermi_lvl_p:f64, potential:f64, temp:f64) -> f64
+    {
+        self.total_charge_field_qf(x, fermi_lvl_n, fermi_lvl_p, potential, 0.0, temp)
+    }
+
+    pub fn total_charge_derivative_pot_qf(&self, x:f64, fermi_lvl_n:f64, fermi_lvl_p:f64, potential:f64, temp:f64) -> f64
+    {
+        self.total_charge_derivative_pot_field_qf(x, fermi_lvl_n, fermi_lvl_p, potential, 0.0, temp)
+    }
+
+    // charge in the local field, which enters the Poole-Frenkel ionization of the dopants
+    pub fn total_charge_field_qf(&self, x:f64, fermi_lvl_n:f64, fermi_lvl_p:f64, potential:f64, field:f64, temp:f64) -> f64
+    {
+        if !self.is_inside(x)
+        {
+            return 0.0;
+        }
+
+        let charge = self.dopant_charge_derivative(x, fermi_lvl_n, fermi_lvl_p, potential, field, temp).0 + 
+            self.trap_charge_derivative(x, fermi_lvl_n, fermi_lvl_p, potential, temp).0;
+
+        charge + self.bulk_at(x).electron_charge(fermi_lvl_n, self.electron_potential(x, potential), temp) + self.bulk_at(x).hole_charge(fermi_lvl_p, self.hole_potential(x, potential), temp)
+    }
+
+    // the field is held fixed, so the derivative leaves out the coupling to the neighbouring nodes
+    pub fn total_charge_derivative_pot_field_qf(&self, x:f64, fermi_lvl_n:f64, fermi_lvl_p:f64, potential:f64, field:f64, temp:f64) -> f64
     {
         if !self.is_inside(x)
         {
             return 0.0;
         }
 
+        let dopant_derivative = self.dopant_charge_derivative(x, fermi_lvl_n, fermi_lvl_p, potential, field, temp).1[0] + 
+            self.trap_charge_derivative(x, fermi_lvl_n, fermi_lvl_p, potential, temp).1[0];
+
+        dopant_derivative + self.bulk_at(x).electron_charge_derivative_pot(fermi_lvl_n, self.electron_potential(x, potential), temp) + self.bulk_at(x).hole_charge_derivative_pot(fermi_lvl_p, self.hole_potential(x, potential), temp)
+    }
+
+    // charge of the ionized dopants and its derivatives wrt [potential, fermi_lvl_n / Q, fermi_lvl_p / Q, field]
+    // donors follow the electron quasi-fermi level and acceptors the hole one, in graded layers the dopant
+    // levels move with the local band edge as an offset of the potential
+    pub fn dopant_charge_derivative(&self, x:f64, fermi_lvl_n:f64, fermi_lvl_p:f64, potential:f64, field:f64, temp:f64) -> (f64, [f64; 4])
+    {
         let mut charge = 0.0;
+        let mut derivative = [0.0; 4];
+
+        if !self.is_inside(x)
+        {
+            return (charge, derivative);
+        }
+
+        let bulk = self.bulk_at(x);
 
         for dopant in self.dopants.iter()
         {
-            charge += dopant.dopant_charge(x);
+            let (fermi_lvl, idx, band_offset) = match dopant.doping_type {
+                Types::Donor => (fermi_lvl_n, 1, bulk.Ec - self.bulk.Ec),
+                Types::Acceptor => (fermi_lvl_p, 2, bulk.Ev - self.bulk.Ev),
+            };
+
+            let (dopant_charge, dopant_derivative, dopant_derivative_field) = dopant.ionized_charge_derivative_pot(x, fermi_lvl, potential - band_offset / constants::Q, field, bulk.epsilon, temp);
+
+            charge += dopant_charge;
+            derivative[0] += dopant_derivative;
+            derivative[idx] += dopant_derivative;
+            derivative[3] += dopant_derivative_field;
         }
 
-        charge + self.bulk.electron_charge(fermi_lvl, potential, temp) + self.bulk.hole_charge(fermi_lvl, potential, temp)
+        (charge, derivative)
     }
 
-    pub fn total_charge_derivative_pot(&self, x:f64, fermi_lvl:f64, potential:f64, temp:f64) -> f64
+    // charge of the trapped carriers and its derivatives wrt [potential, fermi_lvl_n / Q, fermi_lvl_p / Q]
+    pub fn trap_charge_derivative(&self, x:f64, fermi_lvl_n:f64, fermi_lvl_p:f64, potential:f64, temp:f64) -> (f64, [f64; 3])
     {
-        if !self.is_inside(x)
+        if !self.is_inside(x) || self.traps.is_empty()
         {
-            return 0.0;
+            return (0.0, [0.0; 3]);
         }
 
-        self.bulk.electron_charge_derivative_pot(fermi_lvl, potential, temp) + self.bulk.hole_charge_derivative_pot(fermi_lvl, potential, temp)
+        let electron_potential = self.electron_potential(x, potential);
+        let hole_potential = self.hole_potential(x, potential);
+        let n = self.bulk_at(x).electron_conc(fermi_lvl_n, electron_potential, temp);
+        let p = self.bulk_at(x).hole_conc(fermi_lvl_p, hole_potential, temp);
+        let n_derivative = self.bulk_at(x).electron_conc_derivative_pot(fermi_lvl_n, electron_potential, temp);
+        let p_derivative = self.bulk_at(x).hole_conc_derivative_pot(fermi_lvl_p, hole_potential, temp);
+
+        let (charge, charge_derivative) = self.traps.iter()
+            .map(|trap| trap.trap_charge_derivative(self.bulk_at(x), x, n, p, temp))
+            .fold((0.0, [0.0; 2]), |acc, c| (acc.0 + c.0, [acc.1[0] + c.1[0], acc.1[1] + c.1[1]]));
+
+        (
+            charge,
+            [
+                charge_derivative[0] * n_derivative + charge_derivative[1] * p_derivative,
+                charge_derivative[0] * n_derivative,
+                charge_derivative[1] * p_derivative
+            ]
+        )
+    }
+
+    // mean number of trapped electrons per trap at the nodes, one vector per trap, zero outside the layer
+    pub fn trap_occupancy_vec(&self, mesh:&Mesh, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD, temp:f64) -> Vec<VecD>
+    {
+        self.traps.iter().map(|trap| mesh.makeVecFn(|x, i| {
+            if !self.is_inside(x)
+            {
+                return 0.0;
+            }
+
+            let n = self.electron_conc(x, fermi_lvl_n[i], potential[i], temp);
+            let p = self.hole_conc(x, fermi_lvl_p[i], potential[i], temp);
+
+            trap.occupancy(self.bulk_at(x), n, p, temp)
+        })).collect()
     }
 
     // vectorize this?
     pub fn total_charge_vec(&self, mesh:&Mesh, fermi_lvl:f64, potential:&VecD, temp:f64) -> VecD
     {
-        mesh.makeVecFn(|x, i| self.total_charge(x, fermi_lvl, potential[i], temp))
+        self.total_charge_qf_vec(mesh, &mesh.makeVec(fermi_lvl), &mesh.makeVec(fermi_lvl), potential, temp)
     }
 
     pub fn total_charge_derivative_pot_vec(&self, mesh:&Mesh, fermi_lvl:f64, potential:&VecD, temp:f64) -> VecD
     {
-        mesh.makeVecFn(|x, i| self.total_charge_derivative_pot(x, fermi_lvl, potential[i], temp))
+        self.total_charge_derivative_pot_qf_vec(mesh, &mesh.makeVec(fermi_lvl), &mesh.makeVec(fermi_lvl), potential, temp)
+    }
+
+    pub fn total_charge_qf_vec(&self, mesh:&Mesh, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD, potential:&VecD, temp:f64) -> VecD
+    {
+        mesh.makeVecFn(|x, i| self.total_charge_field_qf(x, fermi_lvl_n[i], fermi_lvl_p[i], potential[i], node_field(mesh, potential, i), temp))
+    }
+
+    pub fn total_charge_derivative_pot_qf_vec(&self, mesh:&Mesh, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD, potential:&VecD, temp:f64) -> VecD
+    {
+        mesh.makeVecFn(|x, i| self.total_charge_derivative_pot_field_qf(x, fermi_lvl_n[i], fermi_lvl_p[i], potential[i], node_field(mesh, potential, i), temp))
+    }
+
+    // derivative of the charge wrt the node field, which couples each node to its neighbours
+    pub fn total_charge_derivative_field_qf_vec(&self, mesh:&Mesh, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD, potential:&VecD, temp:f64) -> VecD
+    {
+        mesh.makeVecFn(|x, i| self.dopant_charge_derivative(x, fermi_lvl_n[i], fermi_lvl_p[i], potential[i], node_field(mesh, potential, i), temp).1[3])
+    }
+
+    pub fn electron_conc_derivative_pot(&self, x:f64, fermi_lvl_n:f64, potential:f64, temp:f64) -> f64
+    {
+        if !self.is_inside(x)
+        {
+            return 0.0;
+        }
+
+        self.bulk_at(x).electron_conc_derivative_pot(fermi_lvl_n, self.electron_potential(x, potential), temp)
+    }
+
+    pub fn hole_conc_derivative_pot(&self, x:f64, fermi_lvl_p:f64, potential:f64, temp:f64) -> f64
+    {
+        if !self.is_inside(x)
+        {
+            return 0.0;
+        }
+
+        self.bulk_at(x).hole_conc_derivative_pot(fermi_lvl_p, self.hole_potential(x, potential), temp)
+    }
+
+    pub fn electron_charge_derivative_pot(&self, x:f64, fermi_lvl_n:f64, potential:f64, temp:f64) -> f64
+    {
+        if !self.is_inside(x)
+        {
+            return 0.0;
+        }
+
+        self.bulk_at(x).electron_charge_derivative_pot(fermi_lvl_n, self.electron_potential(x, potential), temp)
+    }
+
+    pub fn hole_charge_derivative_pot(&self, x:f64, fermi_lvl_p:f64, potential:f64, temp:f64) -> f64
+    {
+        if !self.is_inside(x)
+        {
+            return 0.0;
+        }
+
+        self.bulk_at(x).hole_charge_derivative_pot(fermi_lvl_p, self.hole_potential(x, potential), temp)
+    }
+
+    // net recombination rate and its derivatives wrt [potential, fermi_lvl_n / Q, fermi_lvl_p / Q]
+    // the traps add shockley-read-hall recombination through their levels to the bulk models
+    pub fn recombination_derivative(&self, x:f64, fermi_lvl_n:f64, fermi_lvl_p:f64, potential:f64, temp:f64) -> (f64, [f64; 3])
+    {
+        if !self.is_inside(x) || (self.bulk_at(x).recombination.is_empty() && self.traps.is_empty())
+        {
+            return (0.0, [0.0; 3]);
+        }
+
+        let electron_potential = self.electron_potential(x, potential);
+        let hole_potential = self.hole_potential(x, potential);
+        let n = self.bulk_at(x).electron_conc(fermi_lvl_n, electron_potential, temp);
+        let p = self.bulk_at(x).hole_conc(fermi_lvl_p, hole_potential, temp);
+        let n_derivative = self.bulk_at(x).electron_conc_derivative_pot(fermi_lvl_n, electron_potential, temp);
+        let p_derivative = self.bulk_at(x).hole_conc_derivative_pot(fermi_lvl_p, hole_potential, temp);
+
+        let factor = self.traps.iter()
+            .map(|trap| trap.recombination_factor_derivative(self.bulk_at(x), x, n, p, temp))
+            .fold(recombination::total_factor_derivative(&self.bulk_at(x).recombination, self.bulk_at(x), n, p, temp), |acc, f| (acc.0 + f.0, [acc.1[0] + f.1[0], acc.1[1] + f.1[1]]));
+
+        let (rate, rate_derivative) = recombination::net_rate_from_factor(factor, n, p, fermi_lvl_n - fermi_lvl_p, temp);
+
+        (
+            rate,
+            [
+                rate_derivative[0] * n_derivative + rate_derivative[1] * p_derivative,
+                rate_derivative[0] * n_derivative + rate_derivative[2] * constants::Q,
+                rate_derivative[1] * p_derivative - rate_derivative[2] * constants::Q
+            ]
+        )
+    }
+
+    pub fn recombination_vec(&self, mesh:&Mesh, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD, temp:f64) -> VecD
+    {
+        mesh.makeVecFn(|x, i| self.recombination_derivative(x, fermi_lvl_n[i], fermi_lvl_p[i], potential[i], temp).0)
+    }
+
+    // electron and hole current densities through the link [x0, x1]
+    // the link belongs to this layer if its midpoint is inside, both ends use this layer's bulk at their positions
+    pub fn current(&self, x0:f64, x1:f64, potential:(f64, f64), fermi_lvl_n:(f64, f64), fermi_lvl_p:(f64, f64), temp:f64) -> (f64, f64)
+    {
+        (
+            self.electron_current_derivative(x0, x1, potential, fermi_lvl_n, temp).0,
+            self.hole_current_derivative(x0, x1, potential, fermi_lvl_p, temp).0
+        )
+    }
+
+    // electron link current density and its derivatives wrt
+    // [potential0, fermi_lvl_n0 / Q, potential1, fermi_lvl_n1 / Q]
+    pub fn electron_current_derivative(&self, x0:f64, x1:f64, potential:(f64, f64), fermi_lvl_n:(f64, f64), temp:f64) -> (f64, [f64; 4])
+    {
+        if !self.is_inside(0.5 * (x0 + x1))
+        {
+            return (0.0, [0.0; 4]);
+        }
+
+        let thermal_energy = constants::K * temp;
+        let h = x1 - x0;
+        let field = (potential.1 - potential.0) / h;
+        let (mobility, mobility_derivative) = self.electron_mobility_derivative(0.5 * (x0 + x1), field, temp);
+        let prefactor = mobility * thermal_energy;
+
+        let potential = [potential.0, potential.1];
+        let fermi_lvl_n = [fermi_lvl_n.0, fermi_lvl_n.1];
+
+        let electron_potential = [0, 1].map(|k| self.electron_potential([x0, x1][k], potential[k]));
+        let n = [0, 1].map(|k| self.bulk_at([x0, x1][k]).electron_conc(fermi_lvl_n[k], electron_potential[k], temp));
+        let n_derivative = [0, 1].map(|k| self.bulk_at([x0, x1][k]).electron_conc_derivative_pot(fermi_lvl_n[k], electron_potential[k], temp));
+        let u = [0, 1].map(|k| fermi_lvl_n[k] / thermal_energy - n[k].ln());
+
+        let flux_derivative = continuity::sg_flux_derivative(h, u[0], u[1], n[0], n[1]);
+        let mut derivative = [0.0; 4];
+
+        for k in 0..2
+        {
+            // n depends on fermi_lvl_n + Q * potential, so dn/d(fermi_lvl_n / Q) = dn/d(potential)
+            let u_derivative_pot = -n_derivative[k] / n[k];
+            let u_derivative_fermi = constants::Q / thermal_energy - n_derivative[k] / n[k];
+
+            derivative[2 * k] = prefactor * (flux_derivative[k] * u_derivative_pot + flux_derivative[2 + k] * n_derivative[k]);
+            derivative[2 * k + 1] = prefactor * (flux_derivative[k] * u_derivative_fermi + flux_derivative[2 + k] * n_derivative[k]);
+        }
+
+        let flux = continuity::sg_flux(h, u[0], u[1], n[0], n[1]);
+
+        // field dependent mobility, d|E|/d(potential1) = -d|E|/d(potential0) = sign(E) / h
+        let field_derivative = mobility_derivative * thermal_energy * flux * field.signum() / h;
+        derivative[0] -= field_derivative;
+        derivative[2] += field_derivative;
+
+        (prefactor * flux, derivative)
+    }
+
+    // hole link current density and its derivatives wrt
+    // [potential0, fermi_lvl_p0 / Q, potential1, fermi_lvl_p1 / Q]
+    pub fn hole_current_derivative(&self, x0:f64, x1:f64, potential:(f64, f64), fermi_lvl_p:(f64, f64), temp:f64) -> (f64, [f64; 4])
+    {
+        if !self.is_inside(0.5 * (x0 + x1))
+        {
+            return (0.0, [0.0; 4]);
+        }
+
+        let thermal_energy = constants::K * temp;
+        let h = x1 - x0;
+        let field = (potential.1 - potential.0) / h;
+        let (mobility, mobility_derivative) = self.hole_mobility_derivative(0.5 * (x0 + x1), field, temp);
+        let prefactor = -mobility * thermal_energy;
+
+        let potential = [potential.0, potential.1];
+        let fermi_lvl_p = [fermi_lvl_p.0, fermi_lvl_p.1];
+
+        let hole_potential = [0, 1].map(|k| self.hole_potential([x0, x1][k], potential[k]));
+        let p = [0, 1].map(|k| self.bulk_at([x0, x1][k]).hole_conc(fermi_lvl_p[k], hole_potential[k], temp));
+        let p_derivative = [0, 1].map(|k| self.bulk_at([x0, x1][k]).hole_conc_derivative_pot(fermi_lvl_p[k], hole_potential[k], temp));
+        let w = [0, 1].map(|k| -fermi_lvl_p[k] / thermal_energy - p[k].ln());
+
+        let flux_derivative = continuity::sg_flux_derivative(h, w[0], w[1], p[0], p[1]);
+        let mut derivative = [0.0; 4];
+
+        for k in 0..2
+        {
+            // p depends on fermi_lvl_p + Q * potential, so dp/d(fermi_lvl_p / Q) = dp/d(potential)
+            let w_derivative_pot = -p_derivative[k] / p[k];
+            let w_derivative_fermi = -constants::Q / thermal_energy - p_derivative[k] / p[k];
+
+            derivative[2 * k] = prefactor * (flux_derivative[k] * w_derivative_pot + flux_derivative[2 + k] * p_derivative[k]);
+            derivative[2 * k + 1] = prefactor * (flux_derivative[k] * w_derivative_fermi + flux_derivative[2 + k] * p_derivative[k]);
+        }
+
+        let flux = continuity::sg_flux(h, w[0], w[1], p[0], p[1]);
+
+        // field dependent mobility, d|E|/d(potential1) = -d|E|/d(potential0) = sign(E) / h
+        let field_derivative = -mobility_derivative * thermal_energy * flux * field.signum() / h;
+        derivative[0] -= field_derivative;
+        derivative[2] += field_derivative;
+
+        (prefactor * flux, derivative)
+    }
+
+    // impact ionization coefficients (alpha_n, alpha_p) of the link [x0, x1]
+    pub fn ionization_coeffs(&self, x0:f64, x1:f64, potential:(f64, f64)) -> (f64, f64)
+    {
+        if !self.is_inside(0.5 * (x0 + x1))
+        {
+            return (0.0, 0.0);
+        }
+
+        match &self.bulk_at(0.5 * (x0 + x1)).impact_ionization {
+            Some(model) => model.coeffs((potential.1 - potential.0) / (x1 - x0)),
+            None => (0.0, 0.0),
+        }
+    }
+
+    // impact ionization generation rate G = (alpha_n |Jn| + alpha_p |Jp|) / q of the link [x0, x1] and its derivatives wrt
+    // [potential0, fermi_lvl_n0 / Q, fermi_lvl_p0 / Q, potential1, fermi_lvl_n1 / Q, fermi_lvl_p1 / Q]
+    pub fn impact_generation_derivative(&self, x0:f64, x1:f64, potential:(f64, f64), fermi_lvl_n:(f64, f64), fermi_lvl_p:(f64, f64), temp:f64) -> (f64, [f64; 6])
+    {
+        let model = match &self.bulk_at(0.5 * (x0 + x1)).impact_ionization {
+            Some(model) if self.is_inside(0.5 * (x0 + x1)) => model,
+            _ => return (0.0, [0.0; 6]),
+        };
+
+        let h = x1 - x0;
+        let field = -(potential.1 - potential.0) / h;
+
+        let (Jn, dJn) = self.electron_current_derivative(x0, x1, potential, fermi_lvl_n, temp);
+        let (Jp, dJp) = self.hole_current_derivative(x0, x1, potential, fermi_lvl_p, temp);
+        let (alpha_n, alpha_n_derivative) = model.electron.alpha_derivative(field);
+        let (alpha_p, alpha_p_derivative) = model.hole.alpha_derivative(field);
+
+        let generation = (alpha_n * Jn.abs() + alpha_p * Jp.abs()) / constants::Q;
+
+        // derivative wrt potential0 through the field magnitude, it is the opposite for potential1
+        let field_derivative = (alpha_n_derivative * Jn.abs() + alpha_p_derivative * Jp.abs()) / constants::Q * field.signum() / h;
+        let n_scale = alpha_n * Jn.signum() / constants::Q;
+        let p_scale = alpha_p * Jp.signum() / constants::Q;
+
+        (
+            generation,
+            [
+                field_derivative + n_scale * dJn[0] + p_scale * dJp[0],
+                n_scale * dJn[1],
+                p_scale * dJp[1],
+                -field_derivative + n_scale * dJn[2] + p_scale * dJp[2],
+                n_scale * dJn[3],
+                p_scale * dJp[3]
+            ]
+        )
+    }
+
+    // link impact ionization generation rates, like current_vec
+    pub fn impact_generation_vec(&self, mesh:&Mesh, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD, temp:f64) -> VecD
+    {
+        let mut generation = mesh.zeroVec();
+
+        for i in 0..mesh.lastIdx()
+        {
+            (generation[i], _) = self.impact_generation_derivative(
+                mesh.points[i], 
+                mesh.points[i + 1], 
+                (potential[i], potential[i + 1]), 
+                (fermi_lvl_n[i], fermi_lvl_n[i + 1]), 
+                (fermi_lvl_p[i], fermi_lvl_p[i + 1]), 
+                temp
+            );
+        }
+
+        generation
+    }
+
+    // band to band tunneling generation rate at x in the local field, and its derivatives wrt
+    // [field, potential, fermi_lvl_n / Q, fermi_lvl_p / Q]
+    pub fn band_to_band_derivative(&self, x:f64, field:f64, fermi_lvl_n:f64, fermi_lvl_p:f64, potential:f64, temp:f64) -> (f64, [f64; 4])
+    {
+        let model = match self.bulk_at(x).band_to_band {
+            Some(model) if self.is_inside(x) => model,
+            _ => return (0.0, [0.0; 4]),
+        };
+
+        let (kane, kane_derivative) = self.bulk_at(x).kane_generation_derivative(field);
+        let field_derivative = kane_derivative * field.signum();
+
+        match model {
+            BandToBand::Kane => (kane, [field_derivative, 0.0, 0.0, 0.0]),
+            BandToBand::Hurkx => {
+                let electron_potential = self.electron_potential(x, potential);
+                let hole_potential = self.hole_potential(x, potential);
+                let n = self.bulk_at(x).electron_conc(fermi_lvl_n, electron_potential, temp);
+                let p = self.bulk_at(x).hole_conc(fermi_lvl_p, hole_potential, temp);
+                let n_derivative = self.bulk_at(x).electron_conc_derivative_pot(fermi_lvl_n, electron_potential, temp);
+                let p_derivative = self.bulk_at(x).hole_conc_derivative_pot(fermi_lvl_p, hole_potential, temp);
+
+                let (factor, factor_derivative) = tunneling::hurkx_factor_derivative(n, p, fermi_lvl_n - fermi_lvl_p, temp);
+
+                (
+                    kane * factor,
+                    [
+                        field_derivative * factor,
+                        kane * (factor_derivative[0] * n_derivative + factor_derivative[1] * p_derivative),
+                        kane * (factor_derivative[0] * n_derivative + factor_derivative[2] * constants::Q),
+                        kane * (factor_derivative[1] * p_derivative - factor_derivative[2] * constants::Q)
+                    ]
+                )
+            }
+        }
+    }
+
+    // band to band tunneling generation rates at the nodes, the field is the central difference of the potential
+    pub fn band_to_band_vec(&self, mesh:&Mesh, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD, temp:f64) -> VecD
+    {
+        mesh.makeVecFn(|x, i| self.band_to_band_derivative(x, node_field(mesh, potential, i), fermi_lvl_n[i], fermi_lvl_p[i], potential[i], temp).0)
+    }
+
+    // link current densities, J[i] is the current from mesh.points[i] to mesh.points[i + 1]
+    pub fn current_vec(&self, mesh:&Mesh, potential:&VecD, fermi_lvl_n:&VecD, fermi_lvl_p:&VecD, temp:f64) -> (VecD, VecD)
+    {
+        let mut Jn = mesh.zeroVec();
+        let mut Jp = mesh.zeroVec();
+
+        for i in 0..mesh.lastIdx()
+        {
+            (Jn[i], Jp[i]) = self.current(
+                mesh.points[i], 
+                mesh.points[i + 1], 
+                (potential[i], potential[i + 1]), 
+                (fermi_lvl_n[i], fermi_lvl_n[i + 1]), 
+                (fermi_lvl_p[i], fermi_lvl_p[i + 1]), 
+                temp
+            );
+        }
+
+        (Jn, Jp)
+    }
+
+    // link conductances of the electron continuity equation in the slotboom variable
+    // phi_n = exp((Efn - reference) / kT), so that Jn[i] = conductance[i] * (phi_n[i + 1] - phi_n[i])
+    pub fn electron_conductance_vec(&self, mesh:&Mesh, potential:&VecD, fermi_lvl_n:&VecD, reference:f64, temp:f64) -> VecD
+    {
+        let thermal_energy = constants::K * temp;
+
+        let mut conductance = mesh.zeroVec();
+
+        for i in 0..mesh.lastIdx()
+        {
+            if !self.is_inside(0.5 * (mesh.points[i] + mesh.points[i + 1]))
+            {
+                continue;
+            }
+
+            let field = (potential[i + 1] - potential[i]) / (mesh.points[i + 1] - mesh.points[i]);
+            let mobility = self.electron_mobility_derivative(0.5 * (mesh.points[i] + mesh.points[i + 1]), field, temp).0;
+
+            let u0 = (self.bulk_at(mesh.points[i]).electron_effective_Ec(fermi_lvl_n[i], self.electron_potential(mesh.points[i], potential[i]), temp) - reference) / thermal_energy;
+            let u1 = (self.bulk_at(mesh.points[i + 1]).electron_effective_Ec(fermi_lvl_n[i + 1], self.electron_potential(mesh.points[i + 1], potential[i + 1]), temp) - reference) / thermal_energy;
+
+            conductance[i] = mobility * thermal_energy * continuity::sg_conductance(mesh.points[i + 1] - mesh.points[i], u0, u1);
+        }
+
+        conductance
     }
 
-    pub fn current()
+    // link conductances of the hole continuity equation in the slotboom variable
+    // phi_p = exp((reference - Efp) / kT), so that Jp[i] = -conductance[i] * (phi_p[i + 1] - phi_p[i])
+    pub fn hole_conductance_vec(&self, mesh:&Mesh, potential:&VecD, fermi_lvl_p:&VecD, reference:f64, temp:f64) -> VecD
     {
-        ;
+        let thermal_energy = constants::K * temp;
+
+        let mut conductance = mesh.zeroVec();
+
+        for i in 0..mesh.lastIdx()
+        {
+            if !self.is_inside(0.5 * (mesh.points[i] + mesh.points[i + 1]))
+            {
+                continue;
+            }
+
+            let field = (potential[i + 1] - potential[i]) / (mesh.points[i + 1] - mesh.points[i]);
+            let mobility = self.hole_mobility_derivative(0.5 * (mesh.points[i] + mesh.points[i + 1]), field, temp).0;
+
+            let w0 = -(self.bulk_at(mesh.points[i]).hole_effective_Ev(fermi_lvl_p[i], self.hole_potential(mesh.points[i], potential[i]), temp) - reference) / thermal_energy;
+            let w1 = -(self.bulk_at(mesh.points[i + 1]).hole_effective_Ev(fermi_lvl_p[i + 1], self.hole_potential(mesh.points[i + 1], potential[i + 1]), temp) - reference) / thermal_energy;
+
+            conductance[i] = mobility * thermal_energy * continuity::sg_conductance(mesh.points[i + 1] - mesh.points[i], w0, w1);
+        }
+
+        conductance
     }
 
 }
@@ -0,0 +1,166 @@
+use crate::common::*;
+use constants::Q;
+use super::bulk::Bulk;
+
+// charge states of a trap, energies are absolute like the dopant energies
+#[derive(Debug, Clone, Copy)]
+pub enum TrapType
+{
+    // positive when empty, neutral when it holds an electron
+    DonorLike,
+    // neutral when empty, negative when it holds an electron
+    AcceptorLike,
+    // a donor-like level (0/+) at trapE and an acceptor-like level (-/0) at acceptorE,
+    // the two levels are occupied independently
+    Amphoteric{ acceptorE:f64 },
+}
+
+// deep level trap, the occupancy follows the steady state Shockley-Read-Hall statistics
+// f = (cn n + cp p1) / (cn (n + n1) + cp (p + p1)), which is the fermi-dirac occupancy at equilibrium
+// capture coefficients are cn = sigma_n vth_n and cp = sigma_p vth_p with vth = sqrt(3 kT / m)
+#[derive(Debug)]
+pub struct Trap
+{
+    pub sampled_conc:Vec<f64>,
+    pub sampled_x:Vec<f64>,
+    pub interp_mode:interp::Types,
+    pub trap_type:TrapType,
+    pub trapE:f64,
+    pub electron_cross_section:f64,
+    pub hole_cross_section:f64,
+    pub degeneracy:f64,
+}
+
+impl Trap
+{
+    pub fn create(sampled_conc:Vec<f64>, sampled_x:Vec<f64>, interp_mode:interp::Types, trap_type:TrapType, trapE:f64, electron_cross_section:f64, hole_cross_section:f64, degeneracy:f64) -> Trap
+    {
+        Trap {
+            sampled_conc,
+            sampled_x,
+            interp_mode,
+            trap_type,
+            trapE,
+            electron_cross_section,
+            hole_cross_section,
+            degeneracy
+        }
+    }
+
+    pub fn create_donor_like(sampled_conc:Vec<f64>, sampled_x:Vec<f64>, interp_mode:interp::Types, trapE:f64, electron_cross_section:f64, hole_cross_section:f64, degeneracy:f64) -> Trap
+    {
+        Trap::create(sampled_conc, sampled_x, interp_mode, TrapType::DonorLike, trapE, electron_cross_section, hole_cross_section, degeneracy)
+    }
+
+    pub fn create_acceptor_like(sampled_conc:Vec<f64>, sampled_x:Vec<f64>, interp_mode:interp::Types, trapE:f64, electron_cross_section:f64, hole_cross_section:f64, degeneracy:f64) -> Trap
+    {
+        Trap::create(sampled_conc, sampled_x, interp_mode, TrapType::AcceptorLike, trapE, electron_cross_section, hole_cross_section, degeneracy)
+    }
+
+    pub fn create_amphoteric(sampled_conc:Vec<f64>, sampled_x:Vec<f64>, interp_mode:interp::Types, donorE:f64, acceptorE:f64, electron_cross_section:f64, hole_cross_section:f64, degeneracy:f64) -> Trap
+    {
+        Trap::create(sampled_conc, sampled_x, interp_mode, TrapType::Amphoteric{ acceptorE }, donorE, electron_cross_section, hole_cross_section, degeneracy)
+    }
+
+    // get trap conc at position x
+    pub fn trap_conc(&self, x:f64) -> f64
+    {
+        match self.interp_mode {
+            interp::Types::Nearest => interp::nearest1D(x, &self.sampled_conc, &self.sampled_x),
+            interp::Types::Linear => interp::linear1D(x, &self.sampled_conc, &self.sampled_x),
+        }
+    }
+
+    // trap levels as (energy, donor like), the degeneracy enters as a shift of kT ln(g) of the level
+    // donor like levels are empty with weight g and acceptor like levels are occupied with weight g like the dopants
+    fn levels(&self, temp:f64) -> Vec<(f64, bool)>
+    {
+        let degeneracy_shift = constants::K * temp * self.degeneracy.ln();
+
+        match self.trap_type {
+            TrapType::DonorLike => vec![(self.trapE - degeneracy_shift, true)],
+            TrapType::AcceptorLike => vec![(self.trapE + degeneracy_shift, false)],
+            TrapType::Amphoteric{ acceptorE } => vec![(self.trapE - degeneracy_shift, true), (acceptorE + degeneracy_shift, false)],
+        }
+    }
+
+    // capture coefficients (cn, cp) in m^3/s
+    pub fn capture_coeffs(&self, bulk:&Bulk, temp:f64) -> (f64, f64)
+    {
+        let thermal_velocity = |mass:f64| (3.0 * constants::K * temp / mass).sqrt();
+
+        (
+            self.electron_cross_section * thermal_velocity(bulk.electron_properties.effectiveMass),
+            self.hole_cross_section * thermal_velocity(bulk.hole_properties.effectiveMass)
+        )
+    }
+
+    // each level as (occupancy, d occupancy / dn, d occupancy / dp, recombination factor, d factor / dn, d factor / dp, donor like)
+    // the recombination rate through the level is (np - ni^2) * factor with factor = N cn cp / (cn (n + n1) + cp (p + p1))
+    fn level_statistics(&self, bulk:&Bulk, trap_conc:f64, n:f64, p:f64, temp:f64) -> Vec<([f64; 6], bool)>
+    {
+        let (cn, cp) = self.capture_coeffs(bulk, temp);
+
+        self.levels(temp).into_iter().map(|(energy, donor_like)| {
+            // carrier concentrations with the fermi level at the trap level
+            let n1 = bulk.electron_conc(energy, 0.0, temp);
+            let p1 = bulk.hole_conc(energy, 0.0, temp);
+
+            let numerator = cn * n + cp * p1;
+            let denominator = cn * (n + n1) + cp * (p + p1);
+            let occupancy = numerator / denominator;
+
+            let factor = trap_conc * cn * cp / denominator;
+
+            ([
+                occupancy,
+                cn * (cn * n1 + cp * p) / (denominator * denominator),
+                -cp * numerator / (denominator * denominator),
+                factor,
+                -factor * cn / denominator,
+                -factor * cp / denominator
+            ], donor_like)
+        }).collect()
+    }
+
+    // mean number of trapped electrons per trap, 0 to 1 for single level traps and 0 to 2 for amphoteric ones
+    pub fn occupancy(&self, bulk:&Bulk, n:f64, p:f64, temp:f64) -> f64
+    {
+        self.level_statistics(bulk, 1.0, n, p, temp).iter().map(|(level, _)| level[0]).sum()
+    }
+
+    // charge density of the trap at x and its derivatives wrt (n, p)
+    // donor like levels carry +q N (1 - f) and acceptor like levels -q N f
+    pub fn trap_charge_derivative(&self, bulk:&Bulk, x:f64, n:f64, p:f64, temp:f64) -> (f64, [f64; 2])
+    {
+        let trap_conc = self.trap_conc(x);
+
+        if trap_conc == 0.0
+        {
+            return (0.0, [0.0; 2]);
+        }
+
+        self.level_statistics(bulk, trap_conc, n, p, temp).iter().fold((0.0, [0.0; 2]), |acc, (level, donor_like)| {
+            let empty_charge = if *donor_like { Q * trap_conc } else { 0.0 };
+
+            (
+                acc.0 + empty_charge - Q * trap_conc * level[0],
+                [acc.1[0] - Q * trap_conc * level[1], acc.1[1] - Q * trap_conc * level[2]]
+            )
+        })
+    }
+
+    // shockley-read-hall recombination factor through the trap at x and its derivatives wrt (n, p)
+    pub fn recombination_factor_derivative(&self, bulk:&Bulk, x:f64, n:f64, p:f64, temp:f64) -> (f64, [f64; 2])
+    {
+        let trap_conc = self.trap_conc(x);
+
+        if trap_conc == 0.0
+        {
+            return (0.0, [0.0; 2]);
+        }
+
+        self.level_statistics(bulk, trap_conc, n, p, temp).iter()
+            .fold((0.0, [0.0; 2]), |acc, (level, _)| (acc.0 + level[3], [acc.1[0] + level[4], acc.1[1] + level[5]]))
+    }
+}
@@ -0,0 +1,42 @@
+use crate::common::*;
+use super::recombination;
+
+// local band to band tunneling generation models
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BandToBand
+{
+    // Kane rate, G = a E^2 / sqrt(Eg) exp(-b Eg^1.5 / |E|), with a and b from the reduced effective mass
+    Kane,
+    // Hurkx, the Kane rate scaled by (ni^2 - np) / ((n + ni)(p + ni)) so that it vanishes at equilibrium
+    // and becomes recombination under forward bias
+    Hurkx,
+}
+
+// Hurkx factor (ni^2 - np) / ((n + ni)(p + ni)) and its derivatives wrt (n, p, fermi_lvl_n - fermi_lvl_p)
+pub fn hurkx_factor_derivative(n:f64, p:f64, fermi_split:f64, temp:f64) -> (f64, [f64; 3])
+{
+    let thermal_energy = constants::K * temp;
+
+    let (ni_sq, ni_sq_per_n, ni_sq_per_p) = recombination::effective_intrinsic_sq(n, p, fermi_split, temp);
+    let ni = ni_sq.sqrt();
+
+    let numerator = ni_sq - n * p;
+    let denominator = (n + ni) * (p + ni);
+
+    // derivatives of (ni^2, ni) wrt (n, p, fermi_split)
+    let ni_sq_derivative = [ni_sq_per_n, ni_sq_per_p, -ni_sq / thermal_energy];
+    let ni_derivative = ni_sq_derivative.map(|d| if ni > 0.0 { 0.5 * d / ni } else { 0.0 });
+    let n_derivative = [1.0, 0.0, 0.0];
+    let p_derivative = [0.0, 1.0, 0.0];
+
+    let mut derivative = [0.0; 3];
+    for k in 0..3
+    {
+        let numerator_derivative = ni_sq_derivative[k] - n_derivative[k] * p - n * p_derivative[k];
+        let denominator_derivative = (n_derivative[k] + ni_derivative[k]) * (p + ni) + (n + ni) * (p_derivative[k] + ni_derivative[k]);
+
+        derivative[k] = (numerator_derivative * denominator - numerator * denominator_derivative) / (denominator * denominator);
+    }
+
+    (numerator / denominator, derivative)
+}